@@ -1,15 +1,24 @@
 use {
     crate::{
-        config::{
-            Config,
-            WithVersion,
-        },
+        compare::Tolerances,
+        compose,
+        config::Config,
+        engine::Output,
         error::Error,
+        migrate,
+        overrides::{
+            self,
+            Override,
+        },
     },
     anyhow::Result,
     clap::Arg,
     itertools::Itertools,
-    std::str::FromStr,
+    std::{
+        net::SocketAddr,
+        path::Path,
+        str::FromStr,
+    },
 };
 
 #[derive(Debug, Eq, PartialEq)]
@@ -54,10 +63,32 @@ pub(crate) enum Command {
     },
 
     Init,
+    Migrate {
+        file: String,
+        out: Option<String>,
+    },
     Raid {
-        config: Config,
-        campaign: String,
+        config: Box<Config>,
+        campaigns: Vec<String>,
+        concurrent: bool,
         loot: Option<String>,
+        metrics_listen: Option<SocketAddr>,
+        statsd: Option<SocketAddr>,
+        otlp: Option<String>,
+        metrics_prefix: String,
+        timeseries: Option<String>,
+        results: Option<String>,
+        html: Option<String>,
+        output: Output,
+    },
+    Report {
+        input: String,
+        out: String,
+    },
+    Compare {
+        baseline: String,
+        current: String,
+        tolerances: Tolerances,
     },
 }
 
@@ -101,12 +132,171 @@ impl ClapArgumentLoader {
                     ),
             )
             .subcommand(clap::Command::new("init").about("Renders and example configuration to STDOUT."))
+            .subcommand(
+                clap::Command::new("migrate")
+                    .about("Upgrades a configuration file to the version of this CLI.")
+                    .long_about(
+                        "Upgrades a configuration file to the version of this CLI and explains the changes on STDERR. \
+                         Included files are not followed and need to be migrated on their own. Comments are not \
+                         preserved.",
+                    )
+                    .arg(clap::Arg::new("file").short('f').long("file").required(true))
+                    .arg(
+                        clap::Arg::new("out")
+                            .short('o')
+                            .long("out")
+                            .help("Where to write the migrated configuration to. Defaults to STDOUT.")
+                            .required(false),
+                    ),
+            )
+            .subcommand(
+                clap::Command::new("report")
+                    .about("Renders the results of a raid as a single HTML file.")
+                    .arg(
+                        clap::Arg::new("input")
+                            .short('i')
+                            .long("input")
+                            .help("Results written by raid --results.")
+                            .required(true),
+                    )
+                    .arg(clap::Arg::new("out").short('o').long("out").required(true)),
+            )
+            .subcommand(
+                clap::Command::new("compare")
+                    .about("Compares the results of two raids and fails on regressions.")
+                    .long_about(
+                        "Compares the results of two raids (written by raid --results) per campaign and phase and \
+                         exits with an error if the current results regressed beyond the tolerances.",
+                    )
+                    .arg(clap::Arg::new("baseline").required(true))
+                    .arg(clap::Arg::new("current").required(true))
+                    .arg(
+                        clap::Arg::new("throughput-tolerance")
+                            .long("throughput-tolerance")
+                            .help("Allowed drop of requests per second, in percent.")
+                            .default_value("10"),
+                    )
+                    .arg(
+                        clap::Arg::new("latency-tolerance")
+                            .long("latency-tolerance")
+                            .help("Allowed rise of latency percentiles, in percent.")
+                            .default_value("10"),
+                    )
+                    .arg(
+                        clap::Arg::new("error-rate-tolerance")
+                            .long("error-rate-tolerance")
+                            .help("Allowed rise of the error rate, in percentage points.")
+                            .default_value("1"),
+                    )
+                    .arg(
+                        clap::Arg::new("allow-missing")
+                            .long("allow-missing")
+                            .help(
+                                "Do not count phases and latencies of the baseline that the current results lack as \
+                                 regressions.",
+                            )
+                            .action(clap::ArgAction::SetTrue),
+                    ),
+            )
             .subcommand(
                 clap::Command::new("raid")
                     .about("Go on a raid campaign.")
                     .arg(clap::Arg::new("file").short('f').long("file").required(true))
-                    .arg(clap::Arg::new("campaign").short('c').long("campaign").required(true))
-                    .arg(clap::Arg::new("loot").short('l').long("loot").required(false)),
+                    .arg(
+                        clap::Arg::new("campaign")
+                            .short('c')
+                            .long("campaign")
+                            .help("The campaign(s) to go on. Can be given multiple times or comma separated.")
+                            .action(clap::ArgAction::Append)
+                            .value_delimiter(',')
+                            .required_unless_present("all")
+                            .conflicts_with("all"),
+                    )
+                    .arg(
+                        clap::Arg::new("all")
+                            .short('a')
+                            .long("all")
+                            .help("Go on all campaigns in the configuration.")
+                            .num_args(0),
+                    )
+                    .arg(
+                        clap::Arg::new("concurrent")
+                            .long("concurrent")
+                            .help("Go on all campaigns at the same time instead of one after another.")
+                            .num_args(0),
+                    )
+                    .arg(clap::Arg::new("loot").short('l').long("loot").required(false))
+                    .arg(
+                        clap::Arg::new("metrics-listen")
+                            .long("metrics-listen")
+                            .help(
+                                "Serves live metrics in the Prometheus text format on this address (e.g. \
+                                 127.0.0.1:9090).",
+                            )
+                            .required(false),
+                    )
+                    .arg(
+                        clap::Arg::new("statsd")
+                            .long("statsd")
+                            .help("Pushes metrics to a StatsD server on this address (e.g. 127.0.0.1:8125).")
+                            .required(false),
+                    )
+                    .arg(
+                        clap::Arg::new("otlp")
+                            .long("otlp")
+                            .help("Pushes metrics to an OTLP/HTTP collector (e.g. http://localhost:4318).")
+                            .required(false),
+                    )
+                    .arg(
+                        clap::Arg::new("metrics-prefix")
+                            .long("metrics-prefix")
+                            .help("Prefix of the names of pushed metrics.")
+                            .default_value("viking")
+                            .required(false),
+                    )
+                    .arg(
+                        clap::Arg::new("timeseries")
+                            .long("timeseries")
+                            .help(
+                                "Writes aggregates of every report interval (1 second without one) to this file, as \
+                                 CSV if it ends in .csv and as JSON lines otherwise.",
+                            )
+                            .required(false),
+                    )
+                    .arg(
+                        clap::Arg::new("output")
+                            .long("output")
+                            .help(
+                                "How to show the stats while raiding: redraw them on the terminal (tty), append a \
+                                 line per phase and interval (plain), write a JSON object per phase and interval to \
+                                 STDOUT (json) or only show the summary (quiet). Defaults to tty on a terminal and \
+                                 plain otherwise.",
+                            )
+                            .value_parser(["tty", "plain", "json", "quiet"])
+                            .required(false),
+                    )
+                    .arg(
+                        clap::Arg::new("results")
+                            .long("results")
+                            .help("Writes the results of the raid to this file as JSON.")
+                            .required(false),
+                    )
+                    .arg(
+                        clap::Arg::new("html")
+                            .long("html")
+                            .help("Writes a report of the raid to this file as a single HTML page.")
+                            .required(false),
+                    )
+                    .arg(
+                        clap::Arg::new("set")
+                            .short('s')
+                            .long("set")
+                            .help(
+                                "Overrides a configuration value (e.g. phases[0].threads=64). Applied after \
+                                 VIKING_SET__ prefixed environment variables.",
+                            )
+                            .action(clap::ArgAction::Append),
+                    ),
             )
     }
 
@@ -133,26 +323,87 @@ impl ClapArgumentLoader {
                 path: subc.get_one::<String>("out").unwrap().into(),
                 shell: clap_complete::Shell::from_str(subc.get_one::<String>("shell").unwrap().as_str()).unwrap(),
             }
-        } else if let Some(_) = command.subcommand_matches("init") {
+        } else if command.subcommand_matches("init").is_some() {
             Command::Init
+        } else if let Some(subc) = command.subcommand_matches("migrate") {
+            Command::Migrate {
+                file: subc.get_one::<String>("file").unwrap().into(),
+                out: subc.get_one::<String>("out").cloned(),
+            }
+        } else if let Some(subc) = command.subcommand_matches("report") {
+            Command::Report {
+                input: subc.get_one::<String>("input").unwrap().into(),
+                out: subc.get_one::<String>("out").unwrap().into(),
+            }
+        } else if let Some(subc) = command.subcommand_matches("compare") {
+            let tolerance = |name: &str| {
+                let value = subc.get_one::<String>(name).unwrap();
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|v| *v >= 0.0)
+                    .ok_or_else(|| Error::Argument(format!("invalid {} {}", name, value)))
+            };
+            Command::Compare {
+                baseline: subc.get_one::<String>("baseline").unwrap().into(),
+                current: subc.get_one::<String>("current").unwrap().into(),
+                tolerances: Tolerances {
+                    throughput: tolerance("throughput-tolerance")?,
+                    latency: tolerance("latency-tolerance")?,
+                    error_rate: tolerance("error-rate-tolerance")?,
+                    allow_missing: subc.get_flag("allow-missing"),
+                },
+            }
         } else if let Some(subc) = command.subcommand_matches("raid") {
             let config_path = subc.get_one::<String>("file").unwrap();
-            let config_file = std::fs::read_to_string(config_path)?;
-
-            let expected_version = env!("CARGO_PKG_VERSION").split(".").take(2).join(".");
-            let config_version = serde_yaml::from_str::<WithVersion>(&config_file)?.version;
-            if config_version != expected_version {
-                return Err(Error::VersionCompatibility(format!(
-                    "version: {} is not supported by CLI {}",
-                    config_version, expected_version
-                ))
-                .into());
+            let mut config_file = compose::load(Path::new(config_path))?;
+            if let Some(changes) = migrate::ensure_compatible(&mut config_file)? {
+                eprint!("{}", changes);
+                eprintln!("(run viking migrate to update the configuration file)");
+            }
+            let mut overrides = Override::from_env()?;
+            if let Some(v) = subc.get_many::<String>("set") {
+                for v in v {
+                    overrides.push(v.parse::<Override>()?);
+                }
             }
+            let config = overrides::apply(&overrides, &mut config_file)?;
+            let names = subc
+                .get_many::<String>("campaign")
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect::<Vec<_>>();
+            let campaigns = select_campaigns(&config, subc.get_flag("all"), &names)?;
 
             Command::Raid {
-                config: serde_yaml::from_str::<Config>(&config_file)?,
-                campaign: subc.get_one::<String>("campaign").unwrap().to_owned(),
+                config: Box::new(config),
+                campaigns,
+                concurrent: subc.get_flag("concurrent"),
                 loot: subc.get_one::<String>("loot").cloned(),
+                metrics_listen: subc
+                    .get_one::<String>("metrics-listen")
+                    .map(|v| {
+                        v.parse::<SocketAddr>()
+                            .map_err(|_| Error::Argument(format!("invalid metrics address {}", v)))
+                    })
+                    .transpose()?,
+                statsd: subc
+                    .get_one::<String>("statsd")
+                    .map(|v| {
+                        v.parse::<SocketAddr>()
+                            .map_err(|_| Error::Argument(format!("invalid statsd address {}", v)))
+                    })
+                    .transpose()?,
+                otlp: subc.get_one::<String>("otlp").cloned(),
+                metrics_prefix: subc.get_one::<String>("metrics-prefix").unwrap().clone(),
+                timeseries: subc.get_one::<String>("timeseries").cloned(),
+                results: subc.get_one::<String>("results").cloned(),
+                html: subc.get_one::<String>("html").cloned(),
+                output: match subc.get_one::<String>("output") {
+                    | Some(v) => Output::from_name(v)?,
+                    | None => Output::detect(),
+                },
             }
         } else {
             return Err(Error::UnknownCommand.into());
@@ -167,3 +418,44 @@ impl ClapArgumentLoader {
         Ok(callargs)
     }
 }
+
+/// The campaigns to go on: all of them (sorted by name) or the given ones, in
+/// the given order and without duplicates.
+fn select_campaigns(config: &Config, all: bool, names: &[String]) -> Result<Vec<String>> {
+    if all {
+        return Ok(config.campaigns.keys().sorted().cloned().collect());
+    }
+    let campaigns = names.iter().unique().cloned().collect::<Vec<_>>();
+    for campaign in &campaigns {
+        if !config.campaigns.contains_key(campaign) {
+            return Err(Error::Argument(format!("campaign {} does not exist", campaign)).into());
+        }
+    }
+    Ok(campaigns)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_select_campaigns() {
+        let mut config = serde_yaml::from_str::<Config>(include_str!("../res/example.yaml")).unwrap();
+        let main = config.campaigns["main"].clone();
+        config.campaigns.insert("smoke".to_owned(), main.clone());
+        config.campaigns.insert("api".to_owned(), main);
+        let names = |v: &[&str]| v.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            select_campaigns(&config, true, &[]).unwrap(),
+            names(&["api", "main", "smoke"])
+        );
+        // duplicates are dropped, the order of the arguments is kept
+        assert_eq!(
+            select_campaigns(&config, false, &names(&["smoke", "main", "smoke"])).unwrap(),
+            names(&["smoke", "main"])
+        );
+        let error = select_campaigns(&config, false, &names(&["main", "missing"])).unwrap_err();
+        assert_eq!(error.to_string(), "argument campaign missing does not exist");
+    }
+}
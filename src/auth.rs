@@ -0,0 +1,425 @@
+use {
+    crate::{
+        client::Request,
+        config::{
+            Auth,
+            Duration,
+            ValueParser,
+        },
+        error::Error,
+    },
+    anyhow::Result,
+    base64::prelude::*,
+    hmac::{
+        Hmac,
+        Mac,
+    },
+    itertools::Itertools,
+    reqwest::{
+        blocking::Client,
+        header::{
+            HeaderValue,
+            AUTHORIZATION,
+        },
+    },
+    sha2::{
+        Digest,
+        Sha256,
+    },
+    std::{
+        sync::{
+            Arc,
+            Mutex,
+            OnceLock,
+        },
+        time::{
+            SystemTime,
+            UNIX_EPOCH,
+        },
+    },
+};
+
+/// Name of the header that carries the timestamp of a HMAC signed request.
+pub const HMAC_TIMESTAMP_HEADER: &str = "x-timestamp";
+
+/// The current time as the time since the UNIX epoch. Tests set the time
+/// themselves.
+#[derive(Clone)]
+pub struct Clock(Arc<dyn Fn() -> std::time::Duration+Send+Sync>);
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self(Arc::new(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+        }))
+    }
+}
+
+impl Clock {
+    fn now(&self) -> std::time::Duration {
+        (self.0)()
+    }
+}
+
+/// Authenticates the requests of a phase. One authenticator is shared by all
+/// worker threads of a phase so that OAuth2 tokens are only fetched once.
+pub enum Authenticator {
+    Header(HeaderValue),
+    OAuth2 {
+        token_url: String,
+        client_id: String,
+        client_secret: String,
+        scope: Option<String>,
+        refresh_before: Option<Duration>,
+        token: Mutex<Option<Token>>,
+        /// Client for the token endpoint, apart from the clients of the phase
+        /// (and their settings, cookies and connections). Created by the first
+        /// worker thread that needs it.
+        token_client: OnceLock<Client>,
+        clock: Clock,
+    },
+    Hmac {
+        key_id: Option<String>,
+        secret: Vec<u8>,
+        header: String,
+        clock: Clock,
+    },
+}
+
+pub struct Token {
+    value: HeaderValue,
+    /// Since the UNIX epoch.
+    refresh_at: Option<std::time::Duration>,
+}
+
+#[derive(serde::Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+impl Authenticator {
+    pub fn new(config: &Auth) -> Result<Self> {
+        Self::with_clock(config, Clock::default())
+    }
+
+    fn with_clock(config: &Auth, clock: Clock) -> Result<Self> {
+        Ok(match config {
+            | Auth::Basic { username, password } => {
+                let credentials = BASE64_STANDARD.encode(format!("{}:{}", username.value()?, password.value()?));
+                Self::Header(sensitive(&format!("Basic {}", credentials))?)
+            },
+            | Auth::Bearer { token } => Self::Header(sensitive(&format!("Bearer {}", token.value()?))?),
+            | Auth::OAuth2 {
+                token_url,
+                client_id,
+                client_secret,
+                scope,
+                refresh_before,
+            } => {
+                Self::OAuth2 {
+                    token_url: token_url.value()?,
+                    client_id: client_id.value()?,
+                    client_secret: client_secret.value()?,
+                    scope: scope.clone(),
+                    refresh_before: refresh_before.clone(),
+                    token: Mutex::new(None),
+                    token_client: OnceLock::new(),
+                    clock,
+                }
+            },
+            | Auth::Hmac { key_id, secret, header } => {
+                Self::Hmac {
+                    key_id: key_id.as_ref().map(ValueParser::value).transpose()?,
+                    secret: secret.value()?.into_bytes(),
+                    header: header.clone().unwrap_or_else(|| AUTHORIZATION.to_string()),
+                    clock,
+                }
+            },
+        })
+    }
+
+    /// Adds the credentials to a request. OAuth2 tokens are fetched when there
+    /// is no token yet or the current one is about to expire.
+    pub fn apply(&self, request: &mut Request) -> Result<()> {
+        match self {
+            | Self::Header(v) => {
+                request.headers_mut().insert(AUTHORIZATION, v.clone());
+            },
+            | Self::OAuth2 { token, clock, .. } => {
+                let mut token = token.lock().unwrap();
+                let expired = match &*token {
+                    | Some(v) => v.refresh_at.is_some_and(|v| clock.now() >= v),
+                    | None => true,
+                };
+                if expired {
+                    *token = Some(self.fetch_token()?);
+                }
+                request
+                    .headers_mut()
+                    .insert(AUTHORIZATION, token.as_ref().unwrap().value.clone());
+            },
+            | Self::Hmac {
+                key_id,
+                secret,
+                header,
+                clock,
+            } => {
+                let timestamp = clock.now().as_secs().to_string();
+                let signature = sign(secret, &string_to_sign(request, &timestamp));
+                let value = match key_id {
+                    | Some(v) => format!("HMAC-SHA256 KeyId={}, Signature={}", v, signature),
+                    | None => format!("HMAC-SHA256 Signature={}", signature),
+                };
+                request
+                    .headers_mut()
+                    .insert(HMAC_TIMESTAMP_HEADER, HeaderValue::from_str(&timestamp)?);
+                request.headers_mut().insert(
+                    reqwest::header::HeaderName::from_bytes(header.as_bytes())?,
+                    sensitive(&value)?,
+                );
+            },
+        }
+        Ok(())
+    }
+
+    /// Runs the OAuth2 client credentials grant against the token endpoint.
+    fn fetch_token(&self) -> Result<Token> {
+        let Self::OAuth2 {
+            token_url,
+            client_id,
+            client_secret,
+            scope,
+            refresh_before,
+            token_client,
+            clock,
+            ..
+        } = self
+        else {
+            unreachable!()
+        };
+
+        let mut form = vec![("grant_type", "client_credentials")];
+        if let Some(v) = scope {
+            form.push(("scope", v));
+        }
+        let response = token_client
+            .get_or_init(Client::new)
+            .post(token_url)
+            .basic_auth(client_id, Some(client_secret))
+            .form(&form)
+            .send()?;
+        if !response.status().is_success() {
+            return Err(Error::Auth(format!("token endpoint responded with {}", response.status())).into());
+        }
+
+        let response = response.json::<TokenResponse>()?;
+        let refresh_at = response.expires_in.map(|v| {
+            let lifetime = std::time::Duration::from_secs(v);
+            // unless configured, refresh when a tenth of the lifetime is left
+            let margin = match refresh_before {
+                | Some(v) => std::time::Duration::from_millis(v.to_ms()).min(lifetime),
+                | None => lifetime / 10,
+            };
+            clock.now() + lifetime - margin
+        });
+        Ok(Token {
+            value: sensitive(&format!("Bearer {}", response.access_token))?,
+            refresh_at,
+        })
+    }
+}
+
+/// The string a HMAC signature is calculated over:
+///
+/// ```text
+/// METHOD\nPATH\nQUERY\nTIMESTAMP\nHEX(SHA256(BODY))
+/// ```
+///
+/// where QUERY holds the encoded query pairs sorted by key and value, joined
+/// with `&`.
+fn string_to_sign(request: &Request, timestamp: &str) -> String {
+    let query = request
+        .url()
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|v| !v.is_empty())
+        .sorted()
+        .join("&");
+    let body = request.payload().unwrap_or_default();
+    format!(
+        "{}\n{}\n{}\n{}\n{}",
+        request.method(),
+        request.url().path(),
+        query,
+        timestamp,
+        hex(&Sha256::digest(body))
+    )
+}
+
+fn sign(secret: &[u8], data: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+    mac.update(data.as_bytes());
+    hex(&mac.finalize().into_bytes())
+}
+
+fn sensitive(value: &str) -> Result<HeaderValue> {
+    let mut value = HeaderValue::from_str(value)?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|v| format!("{:02x}", v)).join("")
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        reqwest::Method,
+        std::{
+            io::{
+                BufRead,
+                BufReader,
+                Read,
+                Write,
+            },
+            net::TcpListener,
+            sync::atomic::{
+                AtomicU64,
+                AtomicUsize,
+                Ordering,
+            },
+        },
+    };
+
+    /// Serves `{"access_token": "token-<n>", "expires_in": 1}` for every
+    /// request, where n counts the tokens issued so far.
+    fn mock_token_endpoint() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/token", listener.local_addr().unwrap());
+        let issued = Arc::new(AtomicUsize::new(0));
+        let counter = issued.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                let mut authorized = false;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end().to_lowercase();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(v) = line.strip_prefix("content-length: ") {
+                        content_length = v.parse().unwrap();
+                    }
+                    // id:secret
+                    authorized |= line == "authorization: basic awq6c2vjcmv0";
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                assert!(authorized);
+                assert_eq!(String::from_utf8(body).unwrap(), "grant_type=client_credentials");
+
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                let body = format!("{{\"access_token\": \"token-{}\", \"expires_in\": 1}}", n);
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: \
+                     close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        (url, issued)
+    }
+
+    /// A clock that stands still at `ms` (since the UNIX epoch) until it is
+    /// set.
+    fn fixed_clock(ms: u64) -> (Clock, Arc<AtomicU64>) {
+        let now = Arc::new(AtomicU64::new(ms));
+        let time = now.clone();
+        let clock = Clock(Arc::new(move || {
+            std::time::Duration::from_millis(time.load(Ordering::SeqCst))
+        }));
+        (clock, now)
+    }
+
+    #[test]
+    fn test_oauth2_refresh() {
+        let (url, issued) = mock_token_endpoint();
+        let (clock, now) = fixed_clock(1_700_000_000_000);
+        let auth = Authenticator::with_clock(
+            &Auth::OAuth2 {
+                token_url: ValueParser::Static(url),
+                client_id: ValueParser::Static("id".into()),
+                client_secret: ValueParser::Static("secret".into()),
+                scope: None,
+                refresh_before: Some(Duration::MilliSeconds(500)),
+            },
+            clock,
+        )
+        .unwrap();
+        let authorization = |auth: &Authenticator| {
+            let mut request = Request::new(Method::GET, "http://localhost/").unwrap();
+            auth.apply(&mut request).unwrap();
+            request
+                .headers_mut()
+                .get(AUTHORIZATION)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_owned()
+        };
+
+        assert_eq!(authorization(&auth), "Bearer token-0");
+        assert_eq!(authorization(&auth), "Bearer token-0");
+        assert_eq!(issued.load(Ordering::SeqCst), 1);
+        // the token lives for a second and is refreshed 500ms before it expires
+        now.fetch_add(499, Ordering::SeqCst);
+        assert_eq!(authorization(&auth), "Bearer token-0");
+        now.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(authorization(&auth), "Bearer token-1");
+        assert_eq!(issued.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_hmac() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign(b"Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+
+        let (clock, _) = fixed_clock(1_700_000_000_000);
+        let auth = Authenticator::with_clock(
+            &Auth::Hmac {
+                key_id: Some(ValueParser::Static("key".into())),
+                secret: ValueParser::Static("secret".into()),
+                header: None,
+            },
+            clock,
+        )
+        .unwrap();
+        let mut request = Request::new(Method::GET, "http://localhost/items?b=2&a=1").unwrap();
+        auth.apply(&mut request).unwrap();
+
+        assert_eq!(request.headers_mut()[HMAC_TIMESTAMP_HEADER], "1700000000");
+        assert_eq!(
+            string_to_sign(&request, "1700000000"),
+            "GET\n/items\na=1&b=2\n1700000000\ne3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        // HMAC-SHA256 of the string above with the key "secret"
+        assert_eq!(
+            request.headers_mut()[AUTHORIZATION],
+            "HMAC-SHA256 KeyId=key, Signature=54723bf9f8a2e2a0eefbdd06e99ef6e9e33d0449f1656db2bb8d5f9264a83074"
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use {
+    crate::{
+        config::Spec,
+        results::{
+            PhaseResults,
+            RaidResults,
+        },
+    },
+    std::fmt::{
+        Display,
+        Formatter,
+    },
+};
+
+/// How much worse than the baseline a run may be before it counts as a
+/// regression.
+#[derive(Debug, Clone)]
+pub struct Tolerances {
+    /// Drop of requests per second, in percent.
+    pub throughput: f64,
+    /// Rise of latency percentiles, in percent.
+    pub latency: f64,
+    /// Rise of the error rate, in percentage points.
+    pub error_rate: f64,
+    /// Whether phases and latencies of the baseline that the current results
+    /// lack are fine instead of regressions.
+    pub allow_missing: bool,
+}
+
+/// Differences between the phases of two raids.
+pub struct Comparison {
+    pub phases: Vec<PhaseComparison>,
+}
+
+pub struct PhaseComparison {
+    pub label: String,
+    pub deltas: Vec<Delta>,
+    /// Why the phase was not compared, or what to keep in mind.
+    pub note: Option<String>,
+    /// The current results lack the phase or its latency, and missing results
+    /// are not allowed.
+    pub missing: bool,
+}
+
+pub struct Delta {
+    pub metric: &'static str,
+    pub baseline: f64,
+    pub current: f64,
+    pub regression: bool,
+}
+
+impl Comparison {
+    /// Compares the phases of both raids by campaign name and phase index.
+    pub fn new(baseline: &RaidResults, current: &RaidResults, tolerances: &Tolerances) -> Self {
+        let mut phases = Vec::new();
+        let mut names = baseline
+            .campaigns
+            .keys()
+            .chain(current.campaigns.keys())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        for name in names {
+            let (a, b) = (baseline.campaigns.get(name), current.campaigns.get(name));
+            let count = a.map_or(0, |v| v.phases.len()).max(b.map_or(0, |v| v.phases.len()));
+            for p_idx in 0..count {
+                let spec = spec_kind(current, name, p_idx).or_else(|| spec_kind(baseline, name, p_idx));
+                let label = match spec {
+                    | Some(v) => format!("Campaign \"{}\", phase #{} ({})", name, p_idx, v),
+                    | None => format!("Campaign \"{}\", phase #{}", name, p_idx),
+                };
+                let pair = (a.and_then(|v| v.phases.get(p_idx)), b.and_then(|v| v.phases.get(p_idx)));
+                let (deltas, note, missing) = match pair {
+                    | (Some(a), Some(b)) => {
+                        let mut notes = Vec::new();
+                        if spec_kind(baseline, name, p_idx) != spec_kind(current, name, p_idx) {
+                            notes.push("spec differs from the baseline");
+                        }
+                        let missing = a.latency.is_some() && b.latency.is_none();
+                        if missing {
+                            notes.push("no latency in the current results");
+                        }
+                        let note = (!notes.is_empty()).then(|| notes.join(", "));
+                        (Self::phase(a, b, tolerances), note, missing)
+                    },
+                    | (Some(_), None) => (Vec::new(), Some("not in the current results".to_owned()), true),
+                    | (None, _) => (Vec::new(), Some("not in the baseline".to_owned()), false),
+                };
+                phases.push(PhaseComparison {
+                    label,
+                    deltas,
+                    note,
+                    missing: missing && !tolerances.allow_missing,
+                });
+            }
+        }
+        Self { phases }
+    }
+
+    fn phase(baseline: &PhaseResults, current: &PhaseResults, tolerances: &Tolerances) -> Vec<Delta> {
+        let mut deltas = Vec::new();
+        let (a, b) = (baseline.requests_per_second(), current.requests_per_second());
+        deltas.push(Delta {
+            metric: "Requests / second",
+            baseline: a,
+            current: b,
+            regression: b < a * (1.0 - tolerances.throughput / 100.0),
+        });
+        if let (Some(a), Some(b)) = (&baseline.latency, &current.latency) {
+            for (metric, a, b) in [
+                ("p50 (ms)", a.p50_ms, b.p50_ms),
+                ("p90 (ms)", a.p90_ms, b.p90_ms),
+                ("p95 (ms)", a.p95_ms, b.p95_ms),
+                ("p99 (ms)", a.p99_ms, b.p99_ms),
+            ] {
+                deltas.push(Delta {
+                    metric,
+                    baseline: a,
+                    current: b,
+                    regression: b > a * (1.0 + tolerances.latency / 100.0),
+                });
+            }
+        }
+        let (a, b) = (error_rate(baseline), error_rate(current));
+        deltas.push(Delta {
+            metric: "Error rate (%)",
+            baseline: a,
+            current: b,
+            regression: b - a > tolerances.error_rate,
+        });
+        deltas
+    }
+
+    /// Number of metrics beyond their tolerance and of phases missing results.
+    pub fn regressions(&self) -> usize {
+        let metrics = self
+            .phases
+            .iter()
+            .flat_map(|v| &v.deltas)
+            .filter(|v| v.regression)
+            .count();
+        metrics + self.phases.iter().filter(|v| v.missing).count()
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for phase in &self.phases {
+            writeln!(f, "{}:", phase.label)?;
+            if let Some(v) = &phase.note {
+                writeln!(f, "\t({}){}", v, if phase.missing { "\tREGRESSION" } else { "" })?;
+            }
+            for d in &phase.deltas {
+                let change = d.current - d.baseline;
+                let relative = if d.baseline == 0.0 {
+                    "".to_owned()
+                } else {
+                    format!(" ({:+.1}%)", change / d.baseline * 100.0)
+                };
+                writeln!(
+                    f,
+                    "\t{:<18}\t{:>10.2} -> {:>10.2}\t{:+.2}{}{}",
+                    d.metric,
+                    d.baseline,
+                    d.current,
+                    change,
+                    relative,
+                    if d.regression { "\tREGRESSION" } else { "" }
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Requests marked as errors and request errors, in percent of all requests.
+fn error_rate(phase: &PhaseResults) -> f64 {
+    if phase.count == 0 {
+        return 0.0;
+    }
+    (phase.error + phase.client_error) as f64 / phase.count as f64 * 100.0
+}
+
+/// The kind of spec of a phase, from the configuration in the results.
+fn spec_kind(results: &RaidResults, campaign: &str, p_idx: usize) -> Option<&'static str> {
+    let phase = results.config.get(campaign)?.phases.get(p_idx)?;
+    Some(match phase.spec {
+        | Spec::Get { .. } => "get",
+        | Spec::WebSocket { .. } => "web_socket",
+        | Spec::Stream { .. } => "stream",
+        | Spec::Grpc { .. } => "grpc",
+        | Spec::Tcp { .. } => "tcp",
+        | Spec::Udp { .. } => "udp",
+        | Spec::GraphQl { .. } => "graphql",
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn results(rps: usize, p99_ms: f64, errors: usize) -> RaidResults {
+        serde_json::from_value(serde_json::json!({
+            "elapsed_ms": 1000,
+            "campaigns": {
+                "main": {
+                    "elapsed_ms": 1000,
+                    "phases": [{
+                        "elapsed_ms": 1000, "count": rps, "success": rps - errors, "error": errors,
+                        "client_error": 0,
+                        "latency": {
+                            "count": rps, "min_ms": 1.0, "mean_ms": 10.0, "p50_ms": 10.0, "p90_ms": 20.0,
+                            "p95_ms": 30.0, "p99_ms": p99_ms, "max_ms": 100.0,
+                        },
+                    }],
+                },
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_compare() {
+        let tolerances = Tolerances {
+            throughput: 10.0,
+            latency: 10.0,
+            error_rate: 1.0,
+            allow_missing: false,
+        };
+        let baseline = results(100, 50.0, 0);
+
+        let same = Comparison::new(&baseline, &results(95, 54.0, 0), &tolerances);
+        assert_eq!(same.regressions(), 0);
+
+        let worse = Comparison::new(&baseline, &results(80, 60.0, 2), &tolerances);
+        let regressions = worse.phases[0]
+            .deltas
+            .iter()
+            .filter(|v| v.regression)
+            .map(|v| v.metric)
+            .collect::<Vec<_>>();
+        assert_eq!(regressions, vec!["Requests / second", "p99 (ms)", "Error rate (%)"]);
+        assert!(worse.to_string().contains("REGRESSION"));
+
+        let mut other = results(100, 50.0, 0);
+        other
+            .campaigns
+            .insert("new".to_owned(), other.campaigns["main"].clone());
+        let missing = Comparison::new(&baseline, &other, &tolerances);
+        assert_eq!(missing.phases.len(), 2);
+        assert_eq!(missing.phases[1].note.as_deref(), Some("not in the baseline"));
+        assert_eq!(missing.regressions(), 0);
+
+        // phases of the baseline that the current results lack regress
+        let removed = Comparison::new(&other, &baseline, &tolerances);
+        assert_eq!(removed.phases[1].note.as_deref(), Some("not in the current results"));
+        assert_eq!(removed.regressions(), 1);
+        assert!(removed.to_string().contains("(not in the current results)\tREGRESSION"));
+        let allowed = Tolerances {
+            allow_missing: true,
+            ..tolerances.clone()
+        };
+        assert_eq!(Comparison::new(&other, &baseline, &allowed).regressions(), 0);
+    }
+
+    #[test]
+    fn test_missing_latency() {
+        let tolerances = Tolerances {
+            throughput: 10.0,
+            latency: 10.0,
+            error_rate: 1.0,
+            allow_missing: false,
+        };
+        let with = results(100, 50.0, 0);
+        let mut without = results(100, 50.0, 0);
+        without.campaigns.get_mut("main").unwrap().phases[0].latency = None;
+
+        let lost = Comparison::new(&with, &without, &tolerances);
+        assert_eq!(
+            lost.phases[0].note.as_deref(),
+            Some("no latency in the current results")
+        );
+        assert_eq!(lost.phases[0].deltas.len(), 2);
+        assert_eq!(lost.regressions(), 1);
+
+        // latency only in the current results is nothing to compare against
+        let gained = Comparison::new(&without, &with, &tolerances);
+        assert_eq!((gained.phases[0].note.as_deref(), gained.regressions()), (None, 0));
+
+        let allowed = Tolerances {
+            allow_missing: true,
+            ..tolerances
+        };
+        let lost = Comparison::new(&with, &without, &allowed);
+        assert_eq!((lost.phases[0].note.is_some(), lost.regressions()), (true, 0));
+    }
+}
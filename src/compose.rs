@@ -0,0 +1,297 @@
+use {
+    crate::error::Error,
+    anyhow::Result,
+    serde_yaml::{
+        Mapping,
+        Value,
+    },
+    std::path::{
+        Path,
+        PathBuf,
+    },
+};
+
+/// Loads a configuration file and resolves its `include`s, `defaults` and
+/// `templates` into a plain configuration document.
+pub fn load(path: &Path) -> Result<Value> {
+    let mut doc = load_with_includes(path, &mut Vec::new())?;
+    resolve(&mut doc)?;
+    Ok(doc)
+}
+
+/// Deserializes a composed document. This goes through the YAML text instead
+/// of `serde_yaml::from_value` so that scalars are read as leniently as they
+/// are from a file (e.g. `static: 4000` into a string).
+pub fn deserialize<T: serde::de::DeserializeOwned>(doc: &Value) -> Result<T> {
+    Ok(serde_yaml::from_str::<T>(&serde_yaml::to_string(doc)?)?)
+}
+
+/// Resolves `defaults` and `templates` of an already included document. Both
+/// keys are removed from the document afterwards.
+pub fn resolve(doc: &mut Value) -> Result<()> {
+    let root = doc
+        .as_mapping_mut()
+        .ok_or_else(|| Error::Config("configuration must be a mapping".into()))?;
+    let defaults = match root.remove("defaults") {
+        | Some(Value::Mapping(v)) => v,
+        | Some(_) => return Err(Error::Config("defaults must be a mapping".into()).into()),
+        | None => Mapping::new(),
+    };
+    let templates = root.remove("templates").unwrap_or(Value::Null);
+    let phase_templates = templates.get("phases").and_then(|v| v.as_mapping());
+    let spec_templates = templates.get("specs").and_then(|v| v.as_mapping());
+
+    let campaigns = match root.get_mut("campaigns").and_then(|v| v.as_mapping_mut()) {
+        | Some(v) => v,
+        | None => return Ok(()),
+    };
+    for (campaign_name, campaign) in campaigns.iter_mut() {
+        let phases = match campaign.get_mut("phases").and_then(|v| v.as_sequence_mut()) {
+            | Some(v) => v,
+            | None => continue,
+        };
+        for (p_idx, phase) in phases.iter_mut().enumerate() {
+            let location = format!(
+                "campaign {:?} phase #{}",
+                campaign_name.as_str().unwrap_or_default(),
+                p_idx
+            );
+            let phase = phase
+                .as_mapping_mut()
+                .ok_or_else(|| Error::Config(format!("{} must be a mapping", location)))?;
+
+            let mut resolved = defaults.clone();
+            if let Some(name) = phase.remove("template") {
+                let template = lookup(phase_templates, &name, "phase", &location)?;
+                let template = template
+                    .as_mapping()
+                    .ok_or_else(|| Error::Config(format!("phase template {:?} must be a mapping", name)))?;
+                overlay(&mut resolved, template.clone());
+            }
+            overlay(&mut resolved, std::mem::take(phase));
+
+            if let Some(name) = resolved.get("spec").and_then(|v| v.get("template")).cloned() {
+                let template = lookup(spec_templates, &name, "spec", &location)?;
+                resolved.insert("spec".into(), template.clone());
+            }
+            *phase = resolved;
+        }
+    }
+    Ok(())
+}
+
+/// Deep merges `overlay` into `base`. Mappings are merged key by key for
+/// `depth` levels, everything below that is replaced as a whole.
+pub fn merge(base: &mut Value, overlay: Value, depth: usize) {
+    match (base, overlay) {
+        | (Value::Mapping(base), Value::Mapping(overlay)) if depth > 0 => {
+            for (k, v) in overlay {
+                match base.get_mut(&k) {
+                    | Some(b) => merge(b, v, depth - 1),
+                    | None => {
+                        base.insert(k, v);
+                    },
+                }
+            }
+        },
+        | (base, overlay) => *base = overlay,
+    }
+}
+
+fn load_with_includes(path: &Path, stack: &mut Vec<PathBuf>) -> Result<Value> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| Error::Config(format!("can not read {}: {}", path.display(), e)))?;
+    if stack.contains(&canonical) {
+        return Err(Error::Config(format!("include cycle at {}", path.display())).into());
+    }
+    stack.push(canonical);
+
+    let mut doc = serde_yaml::from_str::<Value>(&std::fs::read_to_string(path)?)?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    resolve_paths(&mut doc, dir);
+    let includes = match doc.as_mapping_mut().and_then(|v| v.remove("include")) {
+        | None => Vec::new(),
+        | Some(Value::String(v)) => vec![v],
+        | Some(Value::Sequence(v)) => {
+            v.into_iter()
+                .map(|v| {
+                    v.as_str()
+                        .map(|v| v.to_owned())
+                        .ok_or_else(|| Error::Config("include entries must be paths".into()))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        },
+        | Some(_) => return Err(Error::Config("include must be a path or a list of paths".into()).into()),
+    };
+
+    let mut base = Value::Mapping(Mapping::new());
+    for include in includes {
+        merge_document(&mut base, load_with_includes(&dir.join(include), stack)?);
+    }
+    merge_document(&mut base, doc);
+
+    stack.pop();
+    Ok(base)
+}
+
+/// Paths of the files of `client.tls`, below the `tls` mapping.
+const TLS_PATHS: &[&[&str]] = &[&["ca"], &["identity", "pem", "cert"], &["identity", "pem", "key"], &[
+    "identity", "pkcs12", "path",
+]];
+
+/// Makes the relative paths of TLS files in `doc` relative to `dir`, the
+/// directory of the file they are written in, wherever client settings are
+/// (phases, defaults and templates).
+fn resolve_paths(doc: &mut Value, dir: &Path) {
+    match doc {
+        | Value::Mapping(mapping) => {
+            if let Some(tls) = mapping.get_mut("client").and_then(|v| v.get_mut("tls")) {
+                for keys in TLS_PATHS {
+                    let path = keys.iter().try_fold(&mut *tls, |v, k| v.get_mut(*k));
+                    if let Some(Value::String(v)) = path {
+                        if Path::new(v.as_str()).is_relative() {
+                            *v = dir.join(v.as_str()).to_string_lossy().into_owned();
+                        }
+                    }
+                }
+            }
+            mapping.values_mut().for_each(|v| resolve_paths(v, dir));
+        },
+        | Value::Sequence(sequence) => sequence.iter_mut().for_each(|v| resolve_paths(v, dir)),
+        | _ => {},
+    }
+}
+
+/// Merges a whole configuration document. Campaigns, defaults and templates
+/// are merged by name, anything else is replaced.
+fn merge_document(base: &mut Value, overlay: Value) {
+    let (base, overlay) = match (base, overlay) {
+        | (Value::Mapping(base), Value::Mapping(overlay)) => (base, overlay),
+        | (base, overlay) => {
+            *base = overlay;
+            return;
+        },
+    };
+    for (k, v) in overlay {
+        let depth = match k.as_str() {
+            | Some("campaigns") | Some("defaults") => 1,
+            | Some("templates") => 2,
+            | _ => 0,
+        };
+        match base.get_mut(&k) {
+            | Some(b) => merge(b, v, depth),
+            | None => {
+                base.insert(k, v);
+            },
+        }
+    }
+}
+
+/// Sets all keys of `overlay` on `base`, replacing existing ones.
+fn overlay(base: &mut Mapping, overlay: Mapping) {
+    for (k, v) in overlay {
+        base.insert(k, v);
+    }
+}
+
+fn lookup<'a>(templates: Option<&'a Mapping>, name: &Value, kind: &str, location: &str) -> Result<&'a Value> {
+    templates.and_then(|v| v.get(name)).ok_or_else(|| {
+        Error::Config(format!(
+            "{} references unknown {} template {:?}",
+            location,
+            kind,
+            name.as_str().unwrap_or_default()
+        ))
+        .into()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::config::{
+            Config,
+            Identity,
+            ValueParser,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_compose() {
+        let dir = std::env::temp_dir().join(format!("viking-compose-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("base.yaml"), include_str!("../res/example.yaml")).unwrap();
+        std::fs::write(
+            dir.join("overlay.yaml"),
+            r#"
+version: "0.0"
+include:
+  - base.yaml
+defaults:
+  target:
+    static: "http://localhost"
+  threads: 2
+  client:
+    tls:
+      ca: certs/ca.pem
+      identity:
+        pkcs12:
+          path: /etc/viking/client.p12
+templates:
+  phases:
+    burst:
+      threads: 64
+  specs:
+    ping:
+      get:
+        header: {}
+        query: {}
+campaigns:
+  smoke:
+    phases:
+      - template: burst
+        ends:
+          requests: 10
+        timeout:
+          s: 1
+        report: {}
+        spec:
+          template: ping
+        behaviours:
+          ok: []
+          error: {}
+      - threads: 1
+        ends:
+          requests: 10
+        timeout:
+          s: 1
+        report: {}
+        spec:
+          template: ping
+        behaviours:
+          ok: []
+          error: {}
+"#,
+        )
+        .unwrap();
+
+        let config = deserialize::<Config>(&load(&dir.join("overlay.yaml")).unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(config.campaigns.contains_key("main"));
+        let smoke = config.campaigns.get("smoke").unwrap();
+        assert_eq!(smoke.phases[0].threads, 64);
+        assert_eq!(smoke.phases[1].threads, 1);
+        assert!(matches!(&smoke.phases[1].target, ValueParser::Static(v) if v == "http://localhost"));
+        // TLS files are relative to the file that names them
+        let tls = smoke.phases[1].client.as_ref().unwrap().tls.as_ref().unwrap();
+        assert_eq!(tls.ca.as_deref(), Some(dir.join("certs/ca.pem").to_str().unwrap()));
+        assert!(matches!(&tls.identity, Some(Identity::Pkcs12 { path, .. }) if path == "/etc/viking/client.p12"));
+        // defaults only fill in what a phase does not set itself
+        let main = config.campaigns.get("main").unwrap();
+        assert_eq!(main.phases[0].threads, 32);
+        assert!(matches!(&main.phases[0].target, ValueParser::Env(v) if v == "API_URI"));
+    }
+}
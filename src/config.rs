@@ -1,4 +1,11 @@
-use std::collections::HashMap;
+use {
+    crate::error::Error,
+    anyhow::Result,
+    std::{
+        collections::HashMap,
+        net::IpAddr,
+    },
+};
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -18,6 +25,8 @@ pub struct Config {
 #[serde(rename_all = "snake_case")]
 pub struct Campaign {
     pub phases: Vec<Phase>,
+    /// Tags of the pushed metrics of all phases.
+    pub tags: Option<HashMap<String, String>>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -47,6 +56,8 @@ impl Duration {
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct Phase {
+    /// Adjacent phases with the same group run at the same time.
+    pub group: Option<String>,
     pub target: ValueParser,
     pub threads: usize,
     pub ends: End,
@@ -54,6 +65,174 @@ pub struct Phase {
     pub report: Report,
     pub spec: Spec,
     pub behaviours: Behaviours,
+    pub client: Option<Client>,
+    pub auth: Option<Auth>,
+    pub session: Option<Session>,
+    pub trace: Option<Trace>,
+    /// Tags of the pushed metrics of this phase, added to those of the
+    /// campaign.
+    pub tags: Option<HashMap<String, String>>,
+}
+
+/// Gives every worker thread (virtual user) of a phase its own cookie store.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Session {
+    /// Drop all cookies and log in again after this many requests.
+    pub reset_every: Option<usize>,
+    /// Request that is sent at the start of every session.
+    pub login: Option<Login>,
+}
+
+/// Injects W3C trace context headers (`traceparent`, `tracestate`) into the
+/// requests of HTTP phases.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Trace {
+    /// When to start a new trace, `request` by default.
+    pub scope: Option<TraceScope>,
+    /// Value of the `tracestate` header, sent along if set.
+    pub state: Option<String>,
+    /// Whether to set the sampled flag, `true` by default.
+    pub sampled: Option<bool>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceScope {
+    /// Every request is the root span of its own trace.
+    Request,
+    /// All requests of a session (see `Session`) share a trace. Without
+    /// sessions, all requests of a worker thread share one.
+    Session,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Login {
+    pub target: ValueParser,
+    /// HTTP method, `post` by default.
+    pub method: Option<String>,
+    pub header: Option<HashMap<String, Vec<ValueParser>>>,
+    /// URL encoded form body.
+    pub form: Option<HashMap<String, ValueParser>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Auth {
+    Basic {
+        username: ValueParser,
+        password: ValueParser,
+    },
+    Bearer {
+        token: ValueParser,
+    },
+    /// OAuth2 client credentials grant. The token is fetched once per phase
+    /// and refreshed shortly before it expires.
+    #[serde(rename = "oauth2")]
+    OAuth2 {
+        token_url: ValueParser,
+        client_id: ValueParser,
+        client_secret: ValueParser,
+        scope: Option<String>,
+        /// How long before expiry to refresh the token. Defaults to a tenth of
+        /// its lifetime.
+        refresh_before: Option<Duration>,
+    },
+    /// Signs method, path, query, a timestamp and the body of every request
+    /// with HMAC-SHA256.
+    Hmac {
+        key_id: Option<ValueParser>,
+        secret: ValueParser,
+        /// Header that carries the signature, `authorization` by default.
+        header: Option<String>,
+    },
+}
+
+/// Settings of the HTTP client every worker thread of a phase uses.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Client {
+    pub http: Option<HttpVersion>,
+    /// Reuse connections between requests. Disabling this opens a new
+    /// connection per request.
+    pub keep_alive: Option<bool>,
+    pub pool: Option<Pool>,
+    pub compression: Option<Vec<Compression>>,
+    /// Maximum number of redirects to follow, 0 disables redirects.
+    pub redirects: Option<usize>,
+    pub connect_timeout: Option<Duration>,
+    pub tls: Option<Tls>,
+    /// Proxy URL (`http://`, `https://`, `socks5://` or `socks5h://`) to send
+    /// all requests through.
+    pub proxy: Option<ValueParser>,
+    /// Fixed addresses per host name, like curl's `--resolve`. Worker threads
+    /// are spread over the addresses of a host.
+    pub resolve: Option<HashMap<String, Vec<IpAddr>>>,
+    /// Host (name or address) to connect to instead of the host of the target,
+    /// like curl's `--connect-to`. Requests keep the target URL, so its host
+    /// is the TLS server name (SNI) and the `Host` header.
+    pub connect_to: Option<String>,
+    /// Local addresses to send requests from. Worker threads are spread over
+    /// the addresses.
+    pub bind: Option<Vec<IpAddr>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Tls {
+    /// Path to a PEM bundle of additional trusted CA certificates.
+    pub ca: Option<String>,
+    /// Client certificate for mutual TLS.
+    pub identity: Option<Identity>,
+    pub min_version: Option<TlsVersion>,
+    /// Accepts any certificate and host name. Only meant for testing.
+    pub insecure_skip_verify: Option<bool>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Identity {
+    /// PEM encoded certificate (chain) and PKCS#8 key.
+    Pem { cert: String, key: String },
+    Pkcs12 {
+        path: String,
+        password: Option<ValueParser>,
+    },
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum TlsVersion {
+    #[serde(rename = "1.0")]
+    Tls10,
+    #[serde(rename = "1.1")]
+    Tls11,
+    #[serde(rename = "1.2")]
+    Tls12,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HttpVersion {
+    /// HTTP/1.1 only.
+    Http1,
+    /// HTTP/2 with prior knowledge.
+    Http2,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Pool {
+    pub max_idle: Option<usize>,
+    pub idle_timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    Gzip,
+    Brotli,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -63,6 +242,105 @@ pub enum Spec {
         header: HashMap<String, Vec<ValueParser>>,
         query: HashMap<String, Vec<QueryValueParser>>,
     },
+    /// Every thread keeps a WebSocket connection to the target (`ws://` or
+    /// `wss://`) and exchanges messages over it. Behaviours are matched
+    /// against the received messages.
+    WebSocket {
+        header: Option<HashMap<String, Vec<ValueParser>>>,
+        /// Templated text messages, sent in turn. Without messages, threads
+        /// only receive.
+        messages: Option<Vec<ValueParser>>,
+        /// Pause of a thread between two messages.
+        interval: Option<Duration>,
+    },
+    /// Calls a unary or server streaming gRPC method (plaintext HTTP/2).
+    /// Behaviours are matched against the numeric gRPC status code.
+    Grpc {
+        /// `package.Service/Method`
+        method: String,
+        /// `.proto` file that defines the method. Without it, the definitions
+        /// are fetched from the server reflection service of the target.
+        proto: Option<String>,
+        /// Import paths of `proto`, defaults to its directory.
+        includes: Option<Vec<String>>,
+        /// The request message. Its JSON representation is a template.
+        message: Option<serde_json::Value>,
+        metadata: Option<HashMap<String, Vec<ValueParser>>>,
+    },
+    /// Opens long-lived GET requests and reads their body as a stream of
+    /// events (Server-Sent Events or lines) until the server closes it.
+    /// Behaviours are matched against the HTTP status code.
+    Stream {
+        header: Option<HashMap<String, Vec<ValueParser>>>,
+        /// How the body is split into events, `sse` by default.
+        events: Option<StreamEvents>,
+        /// Close a stream after this long and open the next one.
+        lifetime: Option<Duration>,
+    },
+    /// Sends payloads over a TCP connection per thread. Behaviours are matched
+    /// against the responses.
+    Tcp {
+        /// Templated payloads, sent in turn.
+        payloads: Vec<Payload>,
+        /// Without it, responses are not read.
+        read: Option<Read>,
+        /// Reuse the connection between requests, enabled by default.
+        keep_alive: Option<bool>,
+    },
+    /// Sends payloads as UDP datagrams. Behaviours are matched against the
+    /// responses.
+    Udp { payloads: Vec<Payload>, read: Option<Read> },
+    /// Sends GraphQL operations as JSON POST requests. A 200 response with
+    /// errors counts as failed, whatever the behaviours say, unless
+    /// `errors_fail` is `false`.
+    #[serde(rename = "graphql")]
+    GraphQl {
+        header: Option<HashMap<String, Vec<ValueParser>>>,
+        /// Operations, sent in turn.
+        operations: Vec<GraphQlOperation>,
+        /// Count 200 responses with errors as failed, enabled by default.
+        errors_fail: Option<bool>,
+    },
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamEvents {
+    /// Server-Sent Events, every dispatched event counts.
+    Sse,
+    /// Every non-empty line counts, e.g. for NDJSON or long-poll bodies.
+    Lines,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Payload {
+    Text(String),
+    /// Hex encoded bytes, whitespace is ignored.
+    Hex(String),
+}
+
+/// How much of a response to read.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Read {
+    /// Up to and including the delimiter.
+    Delimiter(String),
+    /// Exactly this many bytes.
+    Length(usize),
+    /// Everything until the connection is closed or idle for this long.
+    Idle(Duration),
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct GraphQlOperation {
+    pub query: String,
+    /// Selects the operation to run if the query defines several. Stats are
+    /// broken down by this name.
+    pub operation_name: Option<String>,
+    /// The variables in their JSON form, which is a template.
+    pub variables: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -72,6 +350,17 @@ pub enum ValueParser {
     Env(String),
 }
 
+impl ValueParser {
+    pub fn value(&self) -> Result<String> {
+        match self {
+            | ValueParser::Static(v) => Ok(v.to_owned()),
+            | ValueParser::Env(v) => {
+                std::env::var(v).map_err(|_| Error::Config(format!("environment variable {} is not set", v)).into())
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryValueParser {
@@ -1,10 +1,51 @@
 use {
-    crate::config::{
-        Campaign,
-        Mark,
-        QueryValueParser,
-        Spec,
-        ValueParser,
+    crate::{
+        auth::Authenticator,
+        client::{
+            self,
+            CookieJar,
+            HttpClient,
+            Request,
+        },
+        config::{
+            self,
+            Campaign,
+            End,
+            ErrorBehaviour,
+            GraphQlOperation,
+            Mark,
+            Phase,
+            QueryValueParser,
+            Spec,
+            StreamEvents,
+            ValueParser,
+        },
+        error::Error,
+        failures::{
+            self,
+            FailureResults,
+            Sample,
+        },
+        grpc,
+        metrics::Metrics,
+        redact,
+        results::{
+            CampaignResults,
+            Histogram,
+            OperationResults,
+            PhaseResults,
+            RaidResults,
+            StreamResults,
+            ThreadResults,
+            Timing,
+            WebSocketResults,
+        },
+        socket,
+        stream,
+        template::Templates,
+        timeseries,
+        trace,
+        websocket,
     },
     anyhow::Result,
     crossterm::terminal::{
@@ -12,18 +53,31 @@ use {
         ClearType,
     },
     fancy_regex::Regex,
-    itertools::Itertools,
+    prost_reflect::DynamicMessage,
     reqwest::{
         header::{
             HeaderMap,
             HeaderName,
             HeaderValue,
+            CONTENT_TYPE,
         },
         Method,
         StatusCode,
     },
     std::{
-        collections::BTreeMap,
+        collections::{
+            BTreeMap,
+            HashMap,
+        },
+        io::IsTerminal,
+        path::Path,
+        sync::{
+            atomic::{
+                AtomicUsize,
+                Ordering,
+            },
+            Arc,
+        },
         thread::{
             spawn,
             JoinHandle,
@@ -33,251 +87,1647 @@ use {
 };
 
 #[derive(Debug)]
+pub(crate) enum ThreadEvent {
+    /// A request got a response. Behaviours are matched against `status`
+    /// (e.g. the HTTP status code).
+    /// Responses that may be failures carry a `sample` of them.
+    Success {
+        status: String,
+        /// Raw response of TCP and UDP phases, which behaviours match instead
+        /// of `status`.
+        response: Option<Vec<u8>>,
+        operation: Option<String>,
+        latency: Duration,
+        sample: Option<Box<Sample>>,
+    },
+    /// A request got a response that failed regardless of the behaviours
+    /// (e.g. GraphQL errors).
+    Failure {
+        operation: Option<String>,
+        latency: Duration,
+        sample: Option<Box<Sample>>,
+    },
+    Error {
+        operation: Option<String>,
+        latency: Duration,
+        sample: Option<Box<Sample>>,
+    },
+    /// A request that could not be rendered and was never sent, so it has no
+    /// latency.
+    Unrendered {
+        sample: Box<Sample>,
+    },
+    /// Where the time of an HTTP request went, see `TimingResults`.
+    Timing {
+        dns: Option<Duration>,
+        connect: Option<Duration>,
+        tls: Option<Duration>,
+        ttfb: Duration,
+        transfer: Option<Duration>,
+    },
+    WebSocket(websocket::Event),
+    Stream(stream::Event),
+    Finished,
+}
+
+/// Sends the events of a worker thread to the status loop, tagged with the
+/// phase and thread index.
+pub(crate) struct Reporter {
+    p_idx: usize,
+    t_idx: usize,
+    status_tx: flume::Sender<(usize, usize, ThreadEvent)>,
+    /// Tasks the worker threads of the phase took from the queue.
+    dequeued: Arc<AtomicUsize>,
+}
+
+impl Reporter {
+    pub fn new(
+        p_idx: usize,
+        t_idx: usize,
+        status_tx: flume::Sender<(usize, usize, ThreadEvent)>,
+        dequeued: Arc<AtomicUsize>,
+    ) -> Self {
+        Self {
+            p_idx,
+            t_idx,
+            status_tx,
+            dequeued,
+        }
+    }
+
+    /// Counts a task the worker took from the queue, before it is reported.
+    pub fn dequeued(&self) {
+        self.dequeued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn report(&self, event: ThreadEvent) {
+        self.status_tx.send((self.p_idx, self.t_idx, event)).unwrap();
+    }
+
+    /// Reports a request that the producer could not render as a request
+    /// error. `request` is what it would have been sent to.
+    pub fn unrendered(&self, request: String, error: &anyhow::Error) {
+        self.report(ThreadEvent::Unrendered {
+            sample: Box::new(Sample::error(request, error)),
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Outcome {
+    Success,
+    Error,
+    ClientError,
+}
+
+impl Outcome {
+    /// Name of the outcome in metrics, `unmatched` if no behaviour matched.
+    fn label(outcome: Option<Self>) -> &'static str {
+        match outcome {
+            | Some(Self::Success) => "success",
+            | Some(Self::Error) => "error",
+            | Some(Self::ClientError) => "client_error",
+            | None => "unmatched",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 struct ThreadStats {
     count: usize,
     success: usize,
     error: usize,
     client_error: usize,
+    latency: Histogram,
+    /// Time spent on requests.
+    busy: Duration,
+    /// When the thread finished, since the start of the phase.
+    finished: Option<Duration>,
+    statuses: BTreeMap<String, usize>,
+    web_socket: Option<WebSocketResults>,
+    stream: Option<StreamResults>,
+    timing: Option<Timing>,
+    operations: BTreeMap<String, OperationResults>,
 }
 
-pub struct Engine {}
+impl ThreadStats {
+    fn new(spec: &Spec) -> Self {
+        Self {
+            count: 0,
+            success: 0,
+            error: 0,
+            client_error: 0,
+            latency: Histogram::default(),
+            busy: Duration::ZERO,
+            finished: None,
+            statuses: BTreeMap::new(),
+            web_socket: matches!(spec, Spec::WebSocket { .. }).then(WebSocketResults::default),
+            stream: matches!(spec, Spec::Stream { .. }).then(StreamResults::default),
+            timing: matches!(spec, Spec::Get { .. } | Spec::GraphQl { .. } | Spec::Stream { .. }).then(Timing::default),
+            operations: BTreeMap::new(),
+        }
+    }
 
-impl Engine {
-    pub async fn raid(&self, campaign: &Campaign, recorder: Option<flume::Sender<String>>) -> Result<()> {
-        #[derive(Debug)]
-        enum ThreadEvent {
-            Success { status_code: StatusCode },
-            Error {},
+    /// Counts a request with its outcome (`None` if no behaviour matched) and
+    /// its status, if it is a status code.
+    fn record(&mut self, outcome: Option<Outcome>, operation: Option<String>, status: Option<&str>, latency: Duration) {
+        self.latency.add(latency.as_secs_f64() * 1000.0);
+        self.busy += latency;
+        self.count(outcome, operation, status);
+    }
+
+    /// Counts a request without its latency, e.g. one that was never sent.
+    fn count(&mut self, outcome: Option<Outcome>, operation: Option<String>, status: Option<&str>) {
+        self.count += 1;
+        if let Some(v) = status {
+            *self.statuses.entry(v.to_owned()).or_default() += 1;
         }
+        match outcome {
+            | Some(Outcome::Success) => self.success += 1,
+            | Some(Outcome::Error) => self.error += 1,
+            | Some(Outcome::ClientError) => self.client_error += 1,
+            | None => {},
+        }
+        if let Some(v) = operation {
+            let stats = self.operations.entry(v).or_default();
+            stats.count += 1;
+            match outcome {
+                | Some(Outcome::Success) => stats.success += 1,
+                | Some(Outcome::Error) => stats.error += 1,
+                | Some(Outcome::ClientError) => stats.client_error += 1,
+                | None => {},
+            }
+        }
+    }
 
-        let raid_start = std::time::Instant::now();
+    fn aggregate(data: &BTreeMap<usize, ThreadStats>, elapsed: Duration) -> PhaseResults {
+        PhaseResults {
+            group: None,
+            elapsed_ms: elapsed.as_millis() as u64,
+            count: data.iter().map(|v| v.1.count).sum::<usize>(),
+            success: data.iter().map(|v| v.1.success).sum::<usize>(),
+            error: data.iter().map(|v| v.1.error).sum::<usize>(),
+            client_error: data.iter().map(|v| v.1.client_error).sum::<usize>(),
+            latency: Histogram::merged(data.values().map(|v| &v.latency)).results(),
+            statuses: data.values().fold(BTreeMap::new(), |mut acc, v| {
+                for (status, count) in &v.statuses {
+                    *acc.entry(status.clone()).or_default() += count;
+                }
+                acc
+            }),
+            web_socket: WebSocketResults::merged(data.values().filter_map(|v| v.web_socket.as_ref())),
+            stream: StreamResults::merged(data.values().filter_map(|v| v.stream.as_ref())),
+            timing: Timing::merged(data.values().filter_map(|v| v.timing.as_ref())).map(|v| v.results()),
+            operations: OperationResults::merged(data.values().map(|v| &v.operations)),
+            series: Vec::new(),
+            threads: Vec::new(),
+            failures: BTreeMap::new(),
+        }
+    }
 
-        for phase in &campaign.phases {
-            let phase_start = std::time::Instant::now();
-            let (tasks_tx, tasks_rx) =
-                flume::bounded::<(Method, String, HeaderMap, Vec<(String, String)>, Duration)>(phase.threads * 2);
-            let (status_tx, status_rx) = flume::bounded::<(usize, ThreadEvent)>(phase.threads * 2);
-
-            let mut threads = Vec::<JoinHandle<_>>::with_capacity(phase.threads);
-            let mut thread_stats = BTreeMap::<usize, ThreadStats>::new();
-            for t_idx in 0..phase.threads {
-                let thread_rx = tasks_rx.clone();
-                let thread_status_tx = status_tx.clone();
-                let on_error = phase.behaviours.error.clone();
-
-                let thread = spawn(move || {
-                    let client = reqwest::blocking::Client::new();
-                    for msg in thread_rx.iter() {
-                        let req = client
-                            .request(msg.0, msg.1)
-                            .headers(msg.2)
-                            .query::<Vec<(String, String)>>(&msg.3)
-                            .timeout(msg.4);
-                        let response = req.send();
-
-                        match response {
-                            | Ok(v) => {
-                                thread_status_tx
-                                    .send((t_idx, ThreadEvent::Success {
-                                        status_code: v.status(),
-                                    }))
-                                    .unwrap();
-                            },
-                            | Err(_) => {
-                                thread_status_tx.send((t_idx, ThreadEvent::Error {})).unwrap();
-                                if let Some(v) = &on_error.backoff {
-                                    std::thread::sleep(Duration::from_millis(v.to_ms()));
-                                }
-                            },
-                        }
+    /// Share of its lifetime the thread spent on requests. `elapsed` is the
+    /// time since the start of the phase.
+    fn busy_ratio(&self, elapsed: Duration) -> f64 {
+        let lifetime = self.finished.unwrap_or(elapsed).as_secs_f64();
+        if lifetime == 0.0 {
+            return 0.0;
+        }
+        (self.busy.as_secs_f64() / lifetime).min(1.0)
+    }
+
+    fn threads(data: &BTreeMap<usize, ThreadStats>, elapsed: Duration) -> Vec<ThreadResults> {
+        data.iter()
+            .map(|(t_idx, v)| {
+                ThreadResults {
+                    thread: *t_idx,
+                    count: v.count,
+                    success: v.success,
+                    error: v.error,
+                    client_error: v.client_error,
+                    latency: v.latency.results(),
+                    busy_ratio: v.busy_ratio(elapsed),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Everything a phase needs that can fail to set up, prepared before any phase
+/// of a group starts.
+enum Prepared {
+    Http {
+        target: String,
+        clients: Vec<HttpClient>,
+        /// Cookie stores of the clients, if the phase keeps sessions.
+        cookies: Vec<Arc<CookieJar>>,
+        login: Option<Arc<Login>>,
+        auth: Option<Arc<Authenticator>>,
+        /// Variable templates of GraphQL operations.
+        variables: Option<Templates>,
+        stream: Option<Arc<stream::Stream>>,
+        tracer: Option<trace::Tracer>,
+    },
+    WebSocket {
+        endpoint: Arc<websocket::Endpoint>,
+        messages: Option<Templates>,
+    },
+    Grpc {
+        method: Arc<grpc::Method>,
+        message: Templates,
+    },
+    Socket {
+        endpoint: Arc<socket::Endpoint>,
+        payloads: socket::Payloads,
+    },
+}
+
+/// A request for an HTTP worker thread.
+#[derive(Debug)]
+struct Task {
+    method: Method,
+    url: String,
+    header: HeaderMap,
+    query: Vec<(String, String)>,
+    body: Option<String>,
+    /// Without it, the client timeout applies to every read.
+    timeout: Option<Duration>,
+    /// Name of the GraphQL operation, for GraphQL requests.
+    operation: Option<String>,
+    /// Trace context, set by the worker thread that sends the request.
+    trace: Option<trace::Context>,
+}
+
+#[derive(serde::Deserialize)]
+struct GraphQlResponse {
+    errors: Option<Vec<serde_json::Value>>,
+}
+
+/// A worker thread (virtual user) of a phase. It sends the requests it
+/// receives and reports the outcome back to the status loop.
+struct Worker {
+    reporter: Reporter,
+    /// Target of the phase, for requests that could not be rendered.
+    target: String,
+    client: HttpClient,
+    auth: Option<Arc<Authenticator>>,
+    session: Option<WorkerSession>,
+    /// Reads the body of responses as a stream, for streaming phases.
+    stream: Option<Arc<stream::Stream>>,
+    tracer: Option<trace::Tracer>,
+    /// Records requests as they are sent, with their trace context.
+    recorder: Option<flume::Sender<String>>,
+    on_error: ErrorBehaviour,
+    /// Whether 200 responses to GraphQL operations with errors fail.
+    graphql_errors_fail: bool,
+}
+
+struct WorkerSession {
+    cookies: Arc<CookieJar>,
+    login: Option<Arc<Login>>,
+    reset_every: Option<usize>,
+    requests: usize,
+    active: bool,
+}
+
+impl Worker {
+    fn run(mut self, tasks: flume::Receiver<Result<Task>>) {
+        for task in tasks.iter() {
+            self.reporter.dequeued();
+            let mut task = match task {
+                | Ok(v) => v,
+                | Err(e) => {
+                    self.reporter.unrendered(self.target.clone(), &e);
+                    continue;
+                },
+            };
+            let operation = task.operation.clone();
+            let session = self.begin_session();
+            if let Some(tracer) = &mut self.tracer {
+                let context = tracer.next();
+                context.apply(&mut task.header);
+                task.trace = Some(context);
+            }
+            if let Some(v) = &self.recorder {
+                v.send(format!("{:?}", task)).unwrap();
+            }
+            let request = format!("{} {}", task.method, task.url);
+            let start = std::time::Instant::now();
+            match session.and_then(|_| self.exchange(task, &request, start)) {
+                | Ok(v) => self.reporter.report(v),
+                | Err(e) => {
+                    self.reporter.report(ThreadEvent::Error {
+                        operation,
+                        latency: start.elapsed(),
+                        sample: Some(Box::new(Sample::error(request, &e))),
+                    });
+                    if let Some(v) = &self.on_error.backoff {
+                        std::thread::sleep(Duration::from_millis(v.to_ms()));
                     }
-                });
-                // consumer threads
-                threads.push(thread);
-                thread_stats.insert(t_idx, ThreadStats {
-                    count: 0,
-                    success: 0,
-                    error: 0,
-                    client_error: 0,
-                });
+                },
             }
-            drop(tasks_rx);
-            drop(status_tx);
+        }
+        self.reporter.report(ThreadEvent::Finished);
+    }
 
-            match &phase.spec {
-                | Spec::Get { header, query } => {
-                    let header_map = HeaderMap::from_iter(
-                        header
-                            .iter()
-                            .map(|v| {
-                                (
-                                    v.0.parse().unwrap(),
-                                    v.1.into_iter()
-                                        .map(|v| {
-                                            match v {
-                                                | ValueParser::Static(v) => v.to_owned(),
-                                                | ValueParser::Env(v) => std::env::var(v).unwrap(),
-                                            }
-                                        })
-                                        .join(",")
-                                        .parse()
-                                        .unwrap(),
-                                )
-                            })
-                            .collect::<Vec<(HeaderName, HeaderValue)>>(),
-                    );
+    /// Starts a new session (drops all cookies and logs in) when there is no
+    /// session yet or the current one has seen enough requests.
+    fn begin_session(&mut self) -> Result<()> {
+        let session = match &mut self.session {
+            | Some(v) => v,
+            | None => return Ok(()),
+        };
+        if session.reset_every.is_some_and(|v| session.requests >= v) {
+            session.cookies.reset();
+            session.requests = 0;
+            session.active = false;
+        }
+        if !session.active {
+            if let Some(v) = &mut self.tracer {
+                v.begin_session();
+            }
+            if let Some(login) = &session.login {
+                let request = Request::new(login.method.clone(), &login.target)?
+                    .headers(login.header.clone())
+                    .form(&login.form);
+                let response = self.client.execute(request)?;
+                if response.status().is_client_error() || response.status().is_server_error() {
+                    return Err(Error::Auth(format!("login responded with {}", response.status())).into());
+                }
+            }
+            session.active = true;
+        }
+        session.requests += 1;
+        Ok(())
+    }
 
-                    let mut query_map = query
-                        .iter()
-                        .map(|v| {
-                            (
-                                v.0.clone(),
-                                v.1.into_iter()
-                                    .map(|v| QueryValueParserState::from(v.clone()))
-                                    .collect::<Vec<_>>(),
-                            )
-                        })
-                        .collect::<Vec<_>>();
+    /// Sends `task`, reads the response and returns the event to report for
+    /// it, timed from `start`. Reports the timing of the request on the way.
+    /// Responses with a status other than 2xx are sampled as `request`.
+    fn exchange(&self, task: Task, request: &str, start: std::time::Instant) -> Result<ThreadEvent> {
+        let operation = task.operation.clone();
+        // connections of earlier requests (e.g. the login) do not count
+        self.client.take_timings();
+        let response = self.send(task)?;
+        let headers = start.elapsed();
+        let timings = self.client.take_timings();
+        let ttfb = headers.saturating_sub(timings.total());
+        let status = response.status();
 
-                    let target = match &phase.target {
-                        | ValueParser::Static(v) => v.to_owned(),
-                        | ValueParser::Env(v) => std::env::var(v).unwrap(),
-                    };
+        if let Some(stream) = self.stream.as_ref().filter(|_| status.is_success()) {
+            self.reporter.report(ThreadEvent::Timing {
+                dns: timings.dns,
+                connect: timings.connect,
+                tls: timings.tls,
+                ttfb,
+                transfer: None,
+            });
+            stream.consume(response, start, &self.reporter);
+            return Ok(ThreadEvent::Success {
+                status: status.as_u16().to_string(),
+                response: None,
+                operation,
+                latency: start.elapsed(),
+                sample: None,
+            });
+        }
 
-                    let timeout_ms = phase.timeout.to_ms();
-                    let cond_req = phase.ends.requests.clone();
-                    let cond_time = phase.ends.time.clone();
-                    let thread_recorder = recorder.clone();
+        let header = response.headers().clone();
+        let body = response.bytes()?;
+        self.reporter.report(ThreadEvent::Timing {
+            dns: timings.dns,
+            connect: timings.connect,
+            tls: timings.tls,
+            ttfb,
+            transfer: Some(start.elapsed() - headers),
+        });
+        let latency = start.elapsed();
+        let sample = |status: &str| Some(Box::new(Sample::response(request.to_owned(), status, &header, &body)));
+        if self.graphql_errors_fail && operation.is_some() && status == StatusCode::OK && graphql_errors(&body) {
+            Ok(ThreadEvent::Failure {
+                operation,
+                latency,
+                sample: sample("graphql errors"),
+            })
+        } else {
+            let code = status.as_u16().to_string();
+            Ok(ThreadEvent::Success {
+                sample: if status.is_success() { None } else { sample(&code) },
+                status: code,
+                response: None,
+                operation,
+                latency,
+            })
+        }
+    }
 
-                    spawn(move || {
-                        let mut req_idx = 0_usize;
-                        let start = std::time::Instant::now();
+    fn send(&self, task: Task) -> Result<client::Response> {
+        let mut request = Request::new(task.method, &task.url)?
+            .headers(task.header)
+            .query(&task.query);
+        if let Some(v) = task.timeout {
+            request = request.timeout(v);
+        }
+        if let Some(v) = task.body {
+            request = request
+                .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+                .body(v);
+        }
+        if let Some(auth) = &self.auth {
+            auth.apply(&mut request)?;
+        }
+        self.client.execute(request)
+    }
+}
 
-                        loop {
-                            if let Some(v) = &cond_req {
-                                if req_idx >= *v {
-                                    break;
-                                }
-                            }
-                            if let Some(v) = &cond_time {
-                                if start.elapsed().as_millis() >= v.to_ms() as u128 {
-                                    break;
-                                }
-                            }
+/// Whether a GraphQL response carries errors.
+fn graphql_errors(body: &[u8]) -> bool {
+    serde_json::from_slice::<GraphQlResponse>(body).is_ok_and(|v| v.errors.is_some_and(|v| !v.is_empty()))
+}
 
-                            let mut query_args = Vec::<(String, String)>::new();
-                            for q in &mut query_map {
-                                let mut q_str = "".to_owned();
-                                for q1 in &mut q.1 {
-                                    q_str += &q1.access_string();
-                                }
-                                query_args.push((q.0.clone(), q_str));
-                            }
+/// The login request of a session, resolved once per phase.
+struct Login {
+    method: Method,
+    target: String,
+    header: HeaderMap,
+    form: Vec<(String, String)>,
+}
 
-                            let payload = (
-                                Method::GET,
-                                target.clone(),
-                                header_map.clone(),
-                                query_args,
-                                Duration::from_millis(timeout_ms),
-                            );
-                            match &thread_recorder {
-                                | Some(v) => v.send(format!("{:?}", payload)).unwrap(),
-                                | None => {},
-                            };
-                            tasks_tx.send(payload).unwrap();
-                            req_idx += 1;
-                        }
-                    });
+impl Login {
+    fn new(config: &config::Login) -> Result<Self> {
+        Ok(Self {
+            method: Method::from_bytes(config.method.as_deref().unwrap_or("post").to_uppercase().as_bytes())?,
+            target: config.target.value()?,
+            header: header_map(config.header.as_ref().unwrap_or(&HashMap::new()))?,
+            form: config
+                .form
+                .iter()
+                .flatten()
+                .map(|v| Ok((v.0.clone(), v.1.value()?)))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+/// Builds request headers, joining multiple values of a header with `,`.
+fn header_map(header: &HashMap<String, Vec<ValueParser>>) -> Result<HeaderMap> {
+    let mut map = HeaderMap::new();
+    for (k, v) in header {
+        let value = v.iter().map(ValueParser::value).collect::<Result<Vec<_>>>()?.join(",");
+        map.insert(HeaderName::from_bytes(k.as_bytes())?, HeaderValue::from_str(&value)?);
+    }
+    Ok(map)
+}
+
+/// A phase in flight, as seen by the status loop of its group.
+struct PhaseRun<'a> {
+    phase: &'a Phase,
+    start: std::time::Instant,
+    elapsed: Option<Duration>,
+    finished: usize,
+    threads: Vec<JoinHandle<()>>,
+    stats: BTreeMap<usize, ThreadStats>,
+    behaviours: Vec<(Matcher, &'a Mark)>,
+    /// Whether the status of responses is a code that can be used as a metric
+    /// label (as opposed to a message).
+    status_label: bool,
+    /// Tasks the worker threads took from the queue.
+    dequeued: Arc<AtomicUsize>,
+    /// Requests since the last tick of the time series.
+    window: timeseries::Window,
+    series: Vec<timeseries::Row>,
+    failures: failures::Reservoir,
+    /// Target of the phase, the request of samples the workers do not take.
+    target: String,
+}
+
+/// Matches the status of responses for behaviours.
+enum Matcher {
+    Text(Regex),
+    /// Matches the raw response of TCP and UDP phases, which need not be
+    /// UTF-8. Bytes are matched with `(?-u:\xff)`.
+    Bytes(regex_automata::meta::Regex),
+}
+
+impl Matcher {
+    fn new(spec: &Spec, pattern: &str) -> Result<Self> {
+        match spec {
+            | Spec::Tcp { .. } | Spec::Udp { .. } => {
+                let regex = regex_automata::meta::Regex::builder()
+                    .syntax(regex_automata::util::syntax::Config::new().utf8(false))
+                    .build(pattern)?;
+                Ok(Self::Bytes(regex))
+            },
+            | _ => Ok(Self::Text(Regex::new(pattern)?)),
+        }
+    }
+}
+
+impl PhaseRun<'_> {
+    fn elapsed(&self) -> Duration {
+        self.elapsed.unwrap_or_else(|| self.start.elapsed())
+    }
+
+    fn outcome(&self, status: &str, response: Option<&[u8]>) -> Option<Outcome> {
+        let matches = |matcher: &Matcher| {
+            match matcher {
+                | Matcher::Text(v) => v.is_match(status).unwrap(),
+                | Matcher::Bytes(v) => v.is_match(response.unwrap_or(status.as_bytes())),
+            }
+        };
+        self.behaviours.iter().find(|b| matches(&b.0)).map(|b| {
+            match b.1 {
+                | Mark::Success => Outcome::Success,
+                | Mark::Error => Outcome::Error,
+            }
+        })
+    }
+
+    fn active_threads(&self) -> usize {
+        self.stats.len() - self.finished
+    }
+
+    /// Requests that worker threads picked up and did not finish yet.
+    fn in_flight(&self) -> usize {
+        let finished = self.stats.values().map(|v| v.count).sum::<usize>();
+        self.dequeued.load(Ordering::Relaxed).saturating_sub(finished)
+    }
+}
+
+/// Number of threads with the highest mean latency that the live report
+/// shows.
+const SLOWEST_THREADS: usize = 5;
+
+/// Length of the messages of failure samples in the summary, in characters.
+const FAILURE_MESSAGE_LENGTH: usize = 120;
+
+/// How the stats of running phases are shown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Output {
+    /// Redraws the stats of all phases on the terminal.
+    #[default]
+    Tty,
+    /// Appends a line per phase and report interval.
+    Plain,
+    /// Writes a JSON object per phase and report interval to STDOUT.
+    Json,
+    /// Only shows the summary at the end.
+    Quiet,
+}
+
+impl Output {
+    /// `Tty` if STDOUT and STDERR are terminals, `Plain` otherwise.
+    pub fn detect() -> Self {
+        if std::io::stdout().is_terminal() && std::io::stderr().is_terminal() {
+            Self::Tty
+        } else {
+            Self::Plain
+        }
+    }
+
+    /// Output named `name` on the command line.
+    pub(crate) fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            | "tty" => Ok(Self::Tty),
+            | "plain" => Ok(Self::Plain),
+            | "json" => Ok(Self::Json),
+            | "quiet" => Ok(Self::Quiet),
+            | _ => Err(Error::Argument(format!("unknown output {}", name))),
+        }
+    }
+}
+
+/// A phase in the JSON output.
+#[derive(serde::Serialize)]
+struct PhaseReport<'a> {
+    campaign: &'a str,
+    phase: usize,
+    #[serde(flatten)]
+    results: PhaseResults,
+}
+
+/// Line of a phase in the plain output.
+fn plain_line(campaign: &str, p_idx: usize, data: &PhaseResults) -> String {
+    let latency = match &data.latency {
+        | Some(v) => format!("\tp50: {:.2} ms\tp99: {:.2} ms", v.p50_ms, v.p99_ms),
+        | None => "".to_owned(),
+    };
+    format!(
+        "[{:.1}s] Campaign \"{}\", phase #{}:\tTotal: {}\tOK: {}\tError: {}\tRequest Error: {}\t{:.2} requests / \
+         second{}",
+        data.elapsed_ms as f64 / 1000.0,
+        campaign,
+        p_idx,
+        data.count,
+        data.success,
+        data.error,
+        data.client_error,
+        data.requests_per_second(),
+        latency
+    )
+}
+
+/// Line of a phase in the JSON output.
+fn json_line(campaign: &str, p_idx: usize, results: PhaseResults) -> String {
+    serde_json::to_string(&PhaseReport {
+        campaign,
+        phase: p_idx,
+        results,
+    })
+    .unwrap()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Engine {
+    pub output: Output,
+    /// Live metrics of the raid, if they are served.
+    pub metrics: Option<Arc<Metrics>>,
+    /// Time series file the phases write their aggregates to on every report
+    /// interval.
+    pub timeseries: Option<Arc<timeseries::Writer>>,
+}
+
+impl Engine {
+    /// Raids all given campaigns, either one after another or all at once, and
+    /// collects their results per campaign.
+    pub async fn raid_all(
+        &self,
+        campaigns: Vec<(String, Campaign)>,
+        concurrent: bool,
+        recorder: Option<flume::Sender<String>>,
+    ) -> Result<RaidResults> {
+        let raid_start = std::time::Instant::now();
+        let mut results = BTreeMap::<String, CampaignResults>::new();
+        // results are meant to be shared, so they do not carry secrets
+        let config = campaigns
+            .iter()
+            .map(|(k, v)| (k.clone(), redact::campaign(v)))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut outcomes = Vec::<(String, Result<CampaignResults>)>::with_capacity(campaigns.len());
+        if concurrent {
+            // every campaign would clear the terminal for a frame of its own, so the
+            // campaigns report line by line instead
+            let mut engine = self.clone();
+            if engine.output == Output::Tty {
+                engine.output = Output::Plain;
+            }
+            let mut raids = tokio::task::JoinSet::new();
+            for (name, campaign) in campaigns {
+                let engine = engine.clone();
+                let recorder = recorder.clone();
+                raids.spawn(async move {
+                    let res = engine.raid(&name, &campaign, recorder).await;
+                    (name, res)
+                });
+            }
+            while let Some(raid) = raids.join_next().await {
+                outcomes.push(raid?);
+            }
+        } else {
+            for (name, campaign) in &campaigns {
+                outcomes.push((name.clone(), self.raid(name, campaign, recorder.clone()).await));
+            }
+        }
+
+        // a failed campaign does not stop the others, their results are still
+        // summarized
+        let mut failed = BTreeMap::<String, String>::new();
+        for (name, res) in outcomes {
+            match res {
+                | Ok(v) => {
+                    results.insert(name, v);
                 },
-            };
+                | Err(e) => {
+                    failed.insert(name, format!("{:#}", e));
+                },
+            }
+        }
 
-            let mut behaviours = Vec::<(Regex, &Mark)>::new();
-            for behav in &phase.behaviours.ok {
-                behaviours.push((Regex::new(&behav.match_).unwrap(), &behav.mark));
+        let results = RaidResults {
+            elapsed_ms: raid_start.elapsed().as_millis() as u64,
+            campaigns: results,
+            config,
+        };
+        self.summarize(&results);
+        if !failed.is_empty() {
+            let failed = failed
+                .iter()
+                .map(|(k, v)| format!("{}: {}", k, v))
+                .collect::<Vec<_>>()
+                .join("; ");
+            return Err(Error::Campaign(format!("failed: {}", failed)).into());
+        }
+        Ok(results)
+    }
+
+    pub async fn raid(
+        &self,
+        name: &str,
+        campaign: &Campaign,
+        recorder: Option<flume::Sender<String>>,
+    ) -> Result<CampaignResults> {
+        let raid_start = std::time::Instant::now();
+        let mut phase_results = Vec::<PhaseResults>::with_capacity(campaign.phases.len());
+
+        for group in Self::phase_groups(campaign) {
+            let (status_tx, status_rx) =
+                flume::bounded::<(usize, usize, ThreadEvent)>(group.iter().map(|v| v.1.threads * 2).sum());
+
+            // prepare all phases up front so that no phase of the group starts if one of
+            // them can not
+            let mut prepared = Vec::<Prepared>::with_capacity(group.len());
+            for (_, phase) in &group {
+                prepared.push(Self::prepare(phase).await?);
             }
 
+            let mut runs = BTreeMap::<usize, PhaseRun>::new();
+            for ((p_idx, phase), prepared) in group.iter().zip(prepared) {
+                if let Some(metrics) = &self.metrics {
+                    // phase tags override campaign tags
+                    let tags = campaign.tags.iter().chain(&phase.tags).flatten();
+                    metrics.tags(name, *p_idx, tags.map(|(k, v)| (k.clone(), v.clone())).collect());
+                }
+                runs.insert(
+                    *p_idx,
+                    self.launch(*p_idx, phase, prepared, status_tx.clone(), recorder.clone()),
+                );
+            }
+            drop(status_tx);
+
+            // only the terminal is redrawn on every event, other outputs append
+            let report_interval = group
+                .iter()
+                .map(|v| v.1.report.interval.as_ref().map(|v| v.to_ms()))
+                .min()
+                .flatten()
+                .or_else(|| (self.output != Output::Tty).then(|| timeseries::DEFAULT_INTERVAL.as_millis() as u64));
             let mut report_timer = std::time::Instant::now();
-            self.report(&thread_stats, phase_start.elapsed());
-            for msg in status_rx.iter() {
-                let stats = &mut thread_stats.get_mut(&msg.0).unwrap();
-                match msg.1 {
-                    | ThreadEvent::Success { status_code } => {
-                        stats.count += 1;
-                        let s_code = status_code.as_u16().to_string();
-                        for b in &behaviours {
-                            if b.0.is_match(&s_code).unwrap() {
-                                match b.1 {
-                                    | Mark::Success => stats.success += 1,
-                                    | Mark::Error => stats.error += 1,
-                                }
-                                break;
+            let tick_interval = report_interval.map_or(timeseries::DEFAULT_INTERVAL, Duration::from_millis);
+            let mut tick = std::time::Instant::now();
+            if self.output == Output::Tty {
+                self.report(name, &runs);
+            }
+            loop {
+                // wakes up for ticks of the time series even if no events arrive
+                let remaining = tick_interval.saturating_sub(tick.elapsed());
+                let received = tokio::time::timeout(remaining, status_rx.recv_async()).await.ok();
+                if tick.elapsed() >= tick_interval {
+                    self.tick(name, &mut runs, tick.elapsed())?;
+                    tick = std::time::Instant::now();
+                }
+                let (p_idx, t_idx, event) = match received {
+                    | Some(Ok(v)) => v,
+                    | Some(Err(_)) => break,
+                    | None => {
+                        // appending outputs report on the interval whether or not events arrive
+                        if report_interval.is_some_and(|v| report_timer.elapsed().as_millis() >= v as u128) {
+                            self.report(name, &runs);
+                            report_timer = std::time::Instant::now();
+                        }
+                        continue;
+                    },
+                };
+                let run = runs.get_mut(&p_idx).unwrap();
+                let outcome = match &event {
+                    | ThreadEvent::Success { status, response, .. } => run.outcome(status, response.as_deref()),
+                    | ThreadEvent::Failure { .. } => Some(Outcome::Error),
+                    | ThreadEvent::Error { .. } | ThreadEvent::Unrendered { .. } => Some(Outcome::ClientError),
+                    | _ => None,
+                };
+                if let Some(metrics) = &self.metrics {
+                    match &event {
+                        | ThreadEvent::Success { status, latency, .. } => {
+                            let status = if run.status_label { status.as_str() } else { "" };
+                            metrics.request(name, p_idx, status, Outcome::label(outcome), *latency);
+                        },
+                        | ThreadEvent::Failure { latency, .. } | ThreadEvent::Error { latency, .. } => {
+                            metrics.request(name, p_idx, "", Outcome::label(outcome), *latency);
+                        },
+                        | ThreadEvent::Unrendered { .. } => metrics.count(name, p_idx, "", Outcome::label(outcome)),
+                        | _ => {},
+                    }
+                }
+                match &event {
+                    | ThreadEvent::Success { latency, .. }
+                    | ThreadEvent::Failure { latency, .. }
+                    | ThreadEvent::Error { latency, .. } => {
+                        let error = matches!(outcome, Some(Outcome::Error | Outcome::ClientError));
+                        run.window.add(*latency, error);
+                    },
+                    | ThreadEvent::Unrendered { .. } => run.window.count(true),
+                    | _ => {},
+                }
+
+                let stats = run.stats.get_mut(&t_idx).unwrap();
+                match event {
+                    | ThreadEvent::Success {
+                        status,
+                        response,
+                        operation,
+                        latency,
+                        sample,
+                    } => {
+                        if let Some(Outcome::Error) = outcome {
+                            // messages (e.g. of WebSocket phases) are only known
+                            // to be failures here
+                            let sample = sample.map(|v| *v).or_else(|| {
+                                (!run.status_label).then(|| {
+                                    let message = response.as_deref().unwrap_or(status.as_bytes());
+                                    Sample::message(run.target.clone(), None, &status, message)
+                                })
+                            });
+                            run.failures.add(&status, sample);
+                        }
+                        let status = Some(status.as_str()).filter(|_| run.status_label);
+                        stats.record(outcome, operation, status, latency)
+                    },
+                    | ThreadEvent::Failure {
+                        operation,
+                        latency,
+                        sample,
+                    }
+                    | ThreadEvent::Error {
+                        operation,
+                        latency,
+                        sample,
+                    } => {
+                        let class = sample.as_ref().map_or("request error", |v| v.class.as_str()).to_owned();
+                        run.failures.add(&class, sample.map(|v| *v));
+                        stats.record(outcome, operation, None, latency)
+                    },
+                    | ThreadEvent::Unrendered { sample } => {
+                        run.failures.add(&sample.class.clone(), Some(*sample));
+                        stats.count(outcome, None, None)
+                    },
+                    | ThreadEvent::WebSocket(v) => {
+                        if let Some(stats) = &mut stats.web_socket {
+                            v.record(stats);
+                        }
+                    },
+                    | ThreadEvent::Timing {
+                        dns,
+                        connect,
+                        tls,
+                        ttfb,
+                        transfer,
+                    } => {
+                        if let Some(stats) = &mut stats.timing {
+                            if let Some(v) = dns {
+                                stats.dns.add(v.as_secs_f64() * 1000.0);
+                            }
+                            if let Some(v) = connect {
+                                stats.connect.add(v.as_secs_f64() * 1000.0);
+                            }
+                            if let Some(v) = tls {
+                                stats.tls.add(v.as_secs_f64() * 1000.0);
+                            }
+                            stats.ttfb.add(ttfb.as_secs_f64() * 1000.0);
+                            if let Some(v) = transfer {
+                                stats.transfer.add(v.as_secs_f64() * 1000.0);
                             }
                         }
                     },
-                    | ThreadEvent::Error {} => {
-                        stats.count += 1;
-                        stats.client_error += 1;
+                    | ThreadEvent::Stream(v) => {
+                        if let Some(stats) = &mut stats.stream {
+                            v.record(stats);
+                        }
+                    },
+                    | ThreadEvent::Finished => {
+                        stats.finished = Some(run.start.elapsed());
+                        run.finished += 1;
+                        if run.finished == run.stats.len() {
+                            run.elapsed = Some(run.start.elapsed());
+                        }
                     },
                 };
+                if let Some(metrics) = &self.metrics {
+                    metrics.phase(name, p_idx, run.active_threads(), run.in_flight());
+                }
 
-                if let Some(v) = &phase.report.interval {
-                    if report_timer.elapsed().as_millis() > v.to_ms() as u128 {
-                        self.report(&thread_stats, phase_start.elapsed());
+                if let Some(v) = report_interval {
+                    if report_timer.elapsed().as_millis() > v as u128 {
+                        self.report(name, &runs);
                         report_timer = std::time::Instant::now();
                     }
                 } else {
-                    self.report(&thread_stats, phase_start.elapsed());
+                    self.report(name, &runs);
                     report_timer = std::time::Instant::now();
                 }
             }
 
-            self.report(&thread_stats, phase_start.elapsed());
+            self.report(name, &runs);
+            self.tick(name, &mut runs, tick.elapsed())?;
+
+            for (_, run) in runs {
+                let results = ThreadStats::aggregate(&run.stats, run.elapsed());
+                let threads = ThreadStats::threads(&run.stats, run.elapsed());
+                let results = PhaseResults {
+                    group: run.phase.group.clone(),
+                    series: run.series,
+                    threads,
+                    failures: run.failures.results(),
+                    ..results
+                };
+                for t in run.threads {
+                    t.join().unwrap();
+                }
+                phase_results.push(results);
+            }
+        }
+
+        Ok(CampaignResults {
+            elapsed_ms: raid_start.elapsed().as_millis() as u64,
+            phases: phase_results,
+        })
+    }
+
+    /// Adds a row to the time series of every phase that is still running or
+    /// had requests since the last tick, and writes them to the time series
+    /// file.
+    fn tick(&self, campaign: &str, runs: &mut BTreeMap<usize, PhaseRun>, length: Duration) -> Result<()> {
+        let mut rows = Vec::new();
+        for (p_idx, run) in runs {
+            if run.elapsed.is_some() && run.window.is_empty() {
+                continue;
+            }
+            let elapsed = run.elapsed();
+            let row = timeseries::Row {
+                in_flight: run.in_flight(),
+                active_threads: run.active_threads(),
+                ..run.window.take(campaign, *p_idx, elapsed, length)
+            };
+            run.series.push(row.clone());
+            rows.push(row);
+        }
+        match &self.timeseries {
+            | Some(v) => v.write(&rows),
+            | None => Ok(()),
+        }
+    }
 
-            for t in threads {
-                t.join().unwrap();
+    /// Splits the phases of a campaign into groups that run at the same time.
+    /// Adjacent phases that share the same `group` run concurrently, all other
+    /// phases run on their own.
+    fn phase_groups(campaign: &Campaign) -> Vec<Vec<(usize, &Phase)>> {
+        let mut groups = Vec::<Vec<(usize, &Phase)>>::new();
+        for (p_idx, phase) in campaign.phases.iter().enumerate() {
+            match groups.last_mut() {
+                | Some(last) if phase.group.is_some() && last.last().unwrap().1.group == phase.group => {
+                    last.push((p_idx, phase));
+                },
+                | _ => groups.push(vec![(p_idx, phase)]),
             }
         }
+        groups
+    }
+
+    async fn prepare(phase: &Phase) -> Result<Prepared> {
+        let target = phase.target.value()?;
+        match &phase.spec {
+            | Spec::Get { .. } | Spec::GraphQl { .. } | Spec::Stream { .. } => {
+                let mut clients = Vec::with_capacity(phase.threads);
+                let mut cookies = Vec::with_capacity(phase.threads);
+                let setup = client::Setup::load(&phase.client, &target).await?;
+                for t_idx in 0..phase.threads {
+                    let mut builder = client::builder(&phase.client, &setup, t_idx)?;
+                    if let Spec::Stream { .. } = &phase.spec {
+                        // streams have no overall timeout, the phase timeout applies to every read
+                        builder = builder.read_timeout(Duration::from_millis(phase.timeout.to_ms()));
+                    }
+                    if phase.session.is_some() {
+                        let jar = Arc::new(CookieJar::default());
+                        builder = builder.cookie_provider(jar.clone());
+                        cookies.push(jar);
+                    }
+                    clients.push(builder.build());
+                }
+                let login = match phase.session.as_ref().and_then(|v| v.login.as_ref()) {
+                    | Some(v) => Some(Arc::new(Login::new(v)?)),
+                    | None => None,
+                };
+                Ok(Prepared::Http {
+                    target,
+                    clients,
+                    cookies,
+                    login,
+                    auth: phase.auth.as_ref().map(Authenticator::new).transpose()?.map(Arc::new),
+                    variables: match &phase.spec {
+                        | Spec::GraphQl { operations, .. } => Some(Self::graphql_variables(operations)?),
+                        | _ => None,
+                    },
+                    stream: match &phase.spec {
+                        | Spec::Stream { events, lifetime, .. } => {
+                            Some(Arc::new(stream::Stream {
+                                events: events.clone().unwrap_or(StreamEvents::Sse),
+                                lifetime: lifetime.as_ref().map(|v| Duration::from_millis(v.to_ms())),
+                            }))
+                        },
+                        | _ => None,
+                    },
+                    tracer: phase.trace.as_ref().map(trace::Tracer::new).transpose()?,
+                })
+            },
+            | Spec::WebSocket {
+                header,
+                messages,
+                interval,
+            } => {
+                let messages = match messages {
+                    | Some(v) => {
+                        Some(Templates::new(
+                            &v.iter().map(ValueParser::value).collect::<Result<Vec<_>>>()?,
+                        )?)
+                    },
+                    | None => None,
+                };
+                Ok(Prepared::WebSocket {
+                    endpoint: Arc::new(websocket::Endpoint {
+                        url: target,
+                        header: header_map(header.as_ref().unwrap_or(&HashMap::new()))?,
+                        timeout: Duration::from_millis(phase.timeout.to_ms()),
+                        interval: interval.as_ref().map(|v| Duration::from_millis(v.to_ms())),
+                    }),
+                    messages: messages.filter(|v| !v.is_empty()),
+                })
+            },
+            | Spec::Grpc {
+                method,
+                proto,
+                includes,
+                message,
+                metadata,
+            } => {
+                let pool = match proto {
+                    | Some(v) => grpc::load_proto(Path::new(v), includes.as_deref().unwrap_or_default())?,
+                    | None => grpc::reflect(&target, method.split('/').next().unwrap_or_default()).await?,
+                };
+                let method = grpc::Method::new(
+                    &pool,
+                    &target,
+                    method,
+                    header_map(metadata.as_ref().unwrap_or(&HashMap::new()))?,
+                    Duration::from_millis(phase.timeout.to_ms()),
+                )?;
+                let message = Templates::new(&[serde_json::to_string(
+                    message.as_ref().unwrap_or(&serde_json::json!({})),
+                )?])?;
+                // fail early on messages that do not fit the method
+                method.message(&message.render(0)?)?;
+                Ok(Prepared::Grpc {
+                    method: Arc::new(method),
+                    message,
+                })
+            },
+            | Spec::Tcp {
+                payloads,
+                read,
+                keep_alive,
+            } => {
+                Ok(Prepared::Socket {
+                    endpoint: Arc::new(socket::Endpoint::new(
+                        socket::Protocol::Tcp,
+                        &target,
+                        read.clone(),
+                        Duration::from_millis(phase.timeout.to_ms()),
+                        keep_alive.unwrap_or(true),
+                    )?),
+                    payloads: socket::Payloads::new(payloads)?,
+                })
+            },
+            | Spec::Udp { payloads, read } => {
+                Ok(Prepared::Socket {
+                    endpoint: Arc::new(socket::Endpoint::new(
+                        socket::Protocol::Udp,
+                        &target,
+                        read.clone(),
+                        Duration::from_millis(phase.timeout.to_ms()),
+                        true,
+                    )?),
+                    payloads: socket::Payloads::new(payloads)?,
+                })
+            },
+        }
+    }
 
-        let raid_elapsed = raid_start.elapsed();
-        eprintln!("");
+    /// Spawns the worker threads and the request producer of a phase. Workers
+    /// report back through `status_tx`, tagged with the phase and thread index.
+    fn launch<'a>(
+        &self,
+        p_idx: usize,
+        phase: &'a Phase,
+        prepared: Prepared,
+        status_tx: flume::Sender<(usize, usize, ThreadEvent)>,
+        recorder: Option<flume::Sender<String>>,
+    ) -> PhaseRun<'a> {
+        let start = std::time::Instant::now();
+        let mut threads = Vec::<JoinHandle<_>>::with_capacity(phase.threads);
+        let mut thread_stats = BTreeMap::<usize, ThreadStats>::new();
+        for t_idx in 0..phase.threads {
+            thread_stats.insert(t_idx, ThreadStats::new(&phase.spec));
+        }
+
+        let target = match &prepared {
+            | Prepared::Http { target, .. } => target.clone(),
+            | Prepared::WebSocket { endpoint, .. } => endpoint.url.clone(),
+            | Prepared::Grpc { method, .. } => method.path.to_string(),
+            | Prepared::Socket { endpoint, .. } => endpoint.addr.to_string(),
+        };
+        let dequeued = Arc::new(AtomicUsize::new(0));
+        match (prepared, &phase.spec) {
+            | (
+                Prepared::Http {
+                    target,
+                    clients,
+                    cookies,
+                    login,
+                    auth,
+                    variables,
+                    stream,
+                    tracer,
+                },
+                spec,
+            ) => {
+                let (tasks_tx, tasks_rx) = flume::bounded::<Result<Task>>(phase.threads * 2);
+                let mut cookies = cookies.into_iter();
+                for (t_idx, client) in clients.into_iter().enumerate() {
+                    let worker = Worker {
+                        reporter: Reporter::new(p_idx, t_idx, status_tx.clone(), dequeued.clone()),
+                        target: target.clone(),
+                        client,
+                        auth: auth.clone(),
+                        session: cookies.next().map(|cookies| {
+                            WorkerSession {
+                                cookies,
+                                login: login.clone(),
+                                reset_every: phase.session.as_ref().and_then(|v| v.reset_every),
+                                requests: 0,
+                                active: false,
+                            }
+                        }),
+                        stream: stream.clone(),
+                        tracer: tracer.clone(),
+                        recorder: recorder.clone(),
+                        on_error: phase.behaviours.error.clone(),
+                        graphql_errors_fail: !matches!(spec, Spec::GraphQl {
+                            errors_fail: Some(false),
+                            ..
+                        }),
+                    };
+                    let thread_rx = tasks_rx.clone();
+                    threads.push(spawn(move || worker.run(thread_rx)));
+                }
+
+                // workers record the requests once they carry their trace context
+                let recorder = None;
+                let timeout = Duration::from_millis(phase.timeout.to_ms());
+                match spec {
+                    | Spec::Get { header, query } => {
+                        let header_map = header_map(header).unwrap();
+                        let mut query_map = query
+                            .iter()
+                            .map(|v| {
+                                (
+                                    v.0.clone(),
+                                    v.1.iter()
+                                        .map(|v| QueryValueParserState::from(v.clone()))
+                                        .collect::<Vec<_>>(),
+                                )
+                            })
+                            .collect::<Vec<_>>();
+                        Self::produce(&phase.ends, tasks_tx, recorder, move |_| {
+                            let mut query_args = Vec::<(String, String)>::new();
+                            for q in &mut query_map {
+                                let mut q_str = "".to_owned();
+                                for q1 in &mut q.1 {
+                                    q_str += &q1.access_string();
+                                }
+                                query_args.push((q.0.clone(), q_str));
+                            }
+                            Ok(Task {
+                                method: Method::GET,
+                                url: target.clone(),
+                                header: header_map.clone(),
+                                query: query_args,
+                                body: None,
+                                timeout: Some(timeout),
+                                operation: None,
+                                trace: None,
+                            })
+                        })
+                    },
+                    | Spec::GraphQl { header, operations, .. } => {
+                        let header_map = header_map(header.as_ref().unwrap_or(&HashMap::new())).unwrap();
+                        let variables = variables.unwrap();
+                        let operations = operations.clone();
+                        Self::produce(&phase.ends, tasks_tx, recorder, move |req_idx| {
+                            let o_idx = req_idx % operations.len();
+                            let operation = &operations[o_idx];
+                            let rendered = variables.render(req_idx)?;
+                            let body = serde_json::json!({
+                                "query": operation.query,
+                                "operationName": operation.operation_name,
+                                "variables": serde_json::from_str::<serde_json::Value>(&rendered)?,
+                            });
+                            Ok(Task {
+                                method: Method::POST,
+                                url: target.clone(),
+                                header: header_map.clone(),
+                                query: Vec::new(),
+                                body: Some(body.to_string()),
+                                timeout: Some(timeout),
+                                operation: Some(
+                                    operation
+                                        .operation_name
+                                        .clone()
+                                        .unwrap_or_else(|| format!("#{}", o_idx)),
+                                ),
+                                trace: None,
+                            })
+                        })
+                    },
+                    | Spec::Stream { header, .. } => {
+                        let header_map = header_map(header.as_ref().unwrap_or(&HashMap::new())).unwrap();
+                        Self::produce(&phase.ends, tasks_tx, recorder, move |_| {
+                            Ok(Task {
+                                method: Method::GET,
+                                url: target.clone(),
+                                header: header_map.clone(),
+                                query: Vec::new(),
+                                body: None,
+                                timeout: None,
+                                operation: None,
+                                trace: None,
+                            })
+                        })
+                    },
+                    | _ => unreachable!(),
+                }
+            },
+            | (Prepared::WebSocket { endpoint, messages }, _) => {
+                let (tasks_tx, tasks_rx) = flume::bounded::<Result<Option<String>>>(phase.threads * 2);
+                for t_idx in 0..phase.threads {
+                    let worker = websocket::Worker {
+                        reporter: Reporter::new(p_idx, t_idx, status_tx.clone(), dequeued.clone()),
+                        endpoint: endpoint.clone(),
+                        on_error: phase.behaviours.error.clone(),
+                        socket: None,
+                    };
+                    let thread_rx = tasks_rx.clone();
+                    threads.push(spawn(move || worker.run(thread_rx)));
+                }
+                Self::produce(&phase.ends, tasks_tx, recorder, move |req_idx| {
+                    messages.as_ref().map(|v| v.render(req_idx)).transpose()
+                })
+            },
+            | (Prepared::Grpc { method, message }, _) => {
+                let (tasks_tx, tasks_rx) = flume::bounded::<Result<DynamicMessage>>(phase.threads * 2);
+                for t_idx in 0..phase.threads {
+                    let worker = grpc::Worker {
+                        reporter: Reporter::new(p_idx, t_idx, status_tx.clone(), dequeued.clone()),
+                        method: method.clone(),
+                        on_error: phase.behaviours.error.clone(),
+                    };
+                    let thread_rx = tasks_rx.clone();
+                    threads.push(spawn(move || worker.run(thread_rx)));
+                }
+                Self::produce(&phase.ends, tasks_tx, recorder, move |req_idx| {
+                    method.message(&message.render(req_idx)?)
+                })
+            },
+            | (Prepared::Socket { endpoint, payloads }, _) => {
+                let (tasks_tx, tasks_rx) = flume::bounded::<Result<socket::Bytes>>(phase.threads * 2);
+                for t_idx in 0..phase.threads {
+                    let worker = socket::Worker {
+                        reporter: Reporter::new(p_idx, t_idx, status_tx.clone(), dequeued.clone()),
+                        endpoint: endpoint.clone(),
+                        on_error: phase.behaviours.error.clone(),
+                        connection: None,
+                    };
+                    let thread_rx = tasks_rx.clone();
+                    threads.push(spawn(move || worker.run(thread_rx)));
+                }
+                Self::produce(&phase.ends, tasks_tx, recorder, move |req_idx| payloads.render(req_idx))
+            },
+        }
+
+        let mut behaviours = Vec::<(Matcher, &Mark)>::new();
+        for behav in &phase.behaviours.ok {
+            behaviours.push((Matcher::new(&phase.spec, &behav.match_).unwrap(), &behav.mark));
+        }
+
+        PhaseRun {
+            phase,
+            start,
+            elapsed: None,
+            finished: 0,
+            threads,
+            stats: thread_stats,
+            behaviours,
+            status_label: matches!(
+                phase.spec,
+                Spec::Get { .. } | Spec::GraphQl { .. } | Spec::Stream { .. } | Spec::Grpc { .. }
+            ),
+            dequeued,
+            window: timeseries::Window::default(),
+            failures: failures::Reservoir::default(),
+            series: Vec::new(),
+            target,
+        }
+    }
+
+    /// Templates of the variables of GraphQL operations, one per operation.
+    fn graphql_variables(operations: &[GraphQlOperation]) -> Result<Templates> {
+        if operations.is_empty() {
+            return Err(Error::Config("graphql spec without operations".into()).into());
+        }
+        let variables = operations
+            .iter()
+            .map(|v| serde_json::to_string(v.variables.as_ref().unwrap_or(&serde_json::json!({}))))
+            .collect::<Result<Vec<_>, _>>()?;
+        let templates = Templates::new(&variables)?;
+        for idx in 0..variables.len() {
+            serde_json::from_str::<serde_json::Value>(&templates.render(idx)?)?;
+        }
+        Ok(templates)
+    }
+
+    /// Spawns the producer of a phase. It creates a task per request with
+    /// `next` (given the request index) until one of the end conditions of the
+    /// phase is met. Tasks that can not be rendered are passed on as errors,
+    /// for the worker threads to report them as request errors.
+    fn produce<T: std::fmt::Debug+Send+'static>(
+        ends: &End,
+        tasks_tx: flume::Sender<Result<T>>,
+        recorder: Option<flume::Sender<String>>,
+        mut next: impl FnMut(usize) -> Result<T>+Send+'static,
+    ) {
+        let cond_req = ends.requests;
+        let cond_time = ends.time.clone();
+        spawn(move || {
+            let mut req_idx = 0_usize;
+            let start = std::time::Instant::now();
+
+            loop {
+                if let Some(v) = &cond_req {
+                    if req_idx >= *v {
+                        break;
+                    }
+                }
+                if let Some(v) = &cond_time {
+                    if start.elapsed().as_millis() >= v.to_ms() as u128 {
+                        break;
+                    }
+                }
+
+                let payload =
+                    next(req_idx).map_err(|e| Error::Template(format!("request #{}: {:#}", req_idx, e)).into());
+                if let (Some(v), Ok(payload)) = (&recorder, &payload) {
+                    v.send(format!("{:?}", payload)).unwrap();
+                }
+                tasks_tx.send(payload).unwrap();
+                req_idx += 1;
+            }
+        });
+    }
+
+    fn summarize(&self, results: &RaidResults) {
+        eprintln!();
         eprintln!("=== === ===");
         eprintln!(
             "Raid took {} seconds ({} ms).",
-            raid_elapsed.as_secs(),
-            raid_elapsed.as_millis()
+            results.elapsed_ms / 1000,
+            results.elapsed_ms
         );
+        for (name, campaign) in &results.campaigns {
+            eprintln!();
+            eprintln!(
+                "Campaign \"{}\" took {} seconds ({} ms):",
+                name,
+                campaign.elapsed_ms / 1000,
+                campaign.elapsed_ms
+            );
+            for (p_idx, phase) in campaign.phases.iter().enumerate() {
+                let label = match &phase.group {
+                    | Some(v) => format!("Phase #{} ({})", p_idx, v),
+                    | None => format!("Phase #{}", p_idx),
+                };
+                self.summarize_line(&label, phase);
+                self.report_operations(&phase.operations);
+                self.report_failures(&phase.failures);
+            }
+            self.summarize_line("Combined", &campaign.combined());
+        }
+    }
 
-        Ok(())
+    fn summarize_line(&self, label: &str, data: &PhaseResults) {
+        eprintln!(
+            "{}:\tTotal: {}\tOK: {}\tError: {}\tRequest Error: {}\t{:.2} requests / second",
+            label,
+            data.count,
+            data.success,
+            data.error,
+            data.client_error,
+            data.requests_per_second()
+        );
+    }
+
+    /// Shows the samples of failures on a line each, with the start of the
+    /// body or error message. The full samples are in the results file.
+    fn report_failures(&self, failures: &BTreeMap<String, FailureResults>) {
+        for (class, v) in failures {
+            eprintln!("Failures \"{}\": {}", class, v.count);
+            for sample in &v.samples {
+                let message = sample.message.split_whitespace().collect::<Vec<_>>().join(" ");
+                let short = message.chars().take(FAILURE_MESSAGE_LENGTH).collect::<String>();
+                let ellipsis = if short.len() < message.len() { "…" } else { "" };
+                match &sample.status {
+                    | Some(status) => eprintln!("\t{} -> {}: {}{}", sample.request, status, short, ellipsis),
+                    | None => eprintln!("\t{}: {}{}", sample.request, short, ellipsis),
+                }
+            }
+        }
+    }
+
+    fn report_operations(&self, operations: &BTreeMap<String, OperationResults>) {
+        for (name, v) in operations {
+            eprintln!(
+                "Operation \"{}\":\tTotal: {}\tOK: {}\tError: {}\tRequest Error: {}",
+                name, v.count, v.success, v.error, v.client_error
+            );
+        }
+    }
+
+    fn report(&self, campaign: &str, runs: &BTreeMap<usize, PhaseRun>) {
+        match self.output {
+            | Output::Tty => self.report_tty(campaign, runs),
+            | Output::Plain => {
+                for (p_idx, run) in runs {
+                    eprintln!(
+                        "{}",
+                        plain_line(campaign, *p_idx, &ThreadStats::aggregate(&run.stats, run.elapsed()))
+                    );
+                }
+            },
+            | Output::Json => {
+                for (p_idx, run) in runs {
+                    println!(
+                        "{}",
+                        json_line(campaign, *p_idx, ThreadStats::aggregate(&run.stats, run.elapsed()))
+                    );
+                }
+            },
+            | Output::Quiet => {},
+        }
     }
 
-    fn report(&self, data: &BTreeMap<usize, ThreadStats>, elapsed: Duration) {
+    fn report_tty(&self, campaign: &str, runs: &BTreeMap<usize, PhaseRun>) {
         let stdout = &mut std::io::stdout();
         crossterm::execute!(stdout, Clear(ClearType::All)).unwrap();
-        eprintln!("Stats:");
-        eprintln!("{} requests", data.iter().map(|v| v.1.count).sum::<usize>());
-        eprintln!("{:.2}s elapsed", elapsed.as_secs_f32());
+        for (p_idx, run) in runs {
+            let group = match &run.phase.group {
+                | Some(v) => format!(", group \"{}\"", v),
+                | None => "".to_owned(),
+            };
+            eprintln!("Stats (campaign \"{}\", phase #{}{}):", campaign, p_idx, group);
+            self.report_stats(&ThreadStats::aggregate(&run.stats, run.elapsed()), run.stats.len());
+            eprintln!();
+            eprintln!("Thread details:");
+            self.report_threads(run);
+            eprintln!();
+        }
+        if runs.len() > 1 {
+            let elapsed = runs.values().map(|v| v.elapsed()).max().unwrap();
+            let combined = ThreadStats::aggregate(
+                &runs
+                    .values()
+                    .flat_map(|v| v.stats.values())
+                    .enumerate()
+                    .map(|(t_idx, v)| (t_idx, v.clone()))
+                    .collect(),
+                elapsed,
+            );
+            eprintln!("Combined stats (campaign \"{}\"):", campaign);
+            self.report_stats(&combined, runs.values().map(|v| v.stats.len()).sum());
+        }
+    }
+
+    /// Shows how requests, latency and busy time are distributed over the
+    /// threads of a phase, and the slowest threads.
+    fn report_threads(&self, run: &PhaseRun) {
+        let elapsed = run.elapsed();
+        eprintln!(
+            "Threads: {}, active: {}, with a request in flight: {}",
+            run.stats.len(),
+            run.active_threads(),
+            run.in_flight()
+        );
+        let spread = |mut values: Vec<f64>| {
+            if values.is_empty() {
+                return "-".to_owned();
+            }
+            values.sort_by(f64::total_cmp);
+            format!(
+                "min {:.2} / median {:.2} / max {:.2}",
+                values[0],
+                values[values.len() / 2],
+                values[values.len() - 1]
+            )
+        };
+        let stats = run.stats.values();
+        eprintln!("Requests: {}", spread(stats.clone().map(|v| v.count as f64).collect()));
+        let mean_ms = |v: &ThreadStats| Some(v.latency.summary()).filter(|v| v.count > 0).map(|v| v.mean);
+        eprintln!(
+            "Mean latency (ms): {}",
+            spread(stats.clone().filter_map(mean_ms).collect())
+        );
         eprintln!(
-            "avg {:.2} requests / second",
-            data.iter().map(|v| v.1.count).sum::<usize>() as f32 / elapsed.as_secs_f32()
+            "Busy (%): {}",
+            spread(stats.map(|v| v.busy_ratio(elapsed) * 100.0).collect())
         );
+
+        let mut slowest = run
+            .stats
+            .iter()
+            .filter_map(|(t_idx, v)| mean_ms(v).map(|ms| (t_idx, ms, v)))
+            .collect::<Vec<_>>();
+        slowest.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let slowest = slowest
+            .iter()
+            .take(SLOWEST_THREADS)
+            .map(|(t_idx, ms, v)| format!("#{} ({:.2} ms, {:.1}% busy)", t_idx, ms, v.busy_ratio(elapsed) * 100.0))
+            .collect::<Vec<_>>();
+        if !slowest.is_empty() {
+            eprintln!("Slowest: {}", slowest.join(", "));
+        }
+    }
+
+    fn report_stats(&self, data: &PhaseResults, threads: usize) {
+        let elapsed = data.elapsed_ms as f32 / 1000.0;
+        eprintln!("{} requests", data.count);
+        eprintln!("{:.2}s elapsed", elapsed);
+        eprintln!("avg {:.2} requests / second", data.count as f32 / elapsed);
         eprintln!(
             "avg {:.2} requests / second / thread",
-            data.iter().map(|v| v.1.count).sum::<usize>() as f32 / elapsed.as_secs_f32() / data.len() as f32
+            data.count as f32 / elapsed / threads as f32
         );
         eprintln!(
             "OK: {}, Error: {}, Client error: {}",
-            data.iter().map(|v| v.1.success).sum::<usize>(),
-            data.iter().map(|v| v.1.error).sum::<usize>(),
-            data.iter().map(|v| v.1.client_error).sum::<usize>(),
+            data.success, data.error, data.client_error,
         );
-        eprintln!("");
-        eprintln!("Thread details:");
-        for d in data {
+        if let Some(v) = &data.latency {
+            eprintln!("Latency (ms): {}", v);
+        }
+        if !data.operations.is_empty() {
+            eprintln!();
+            eprintln!("Operation details:");
+            self.report_operations(&data.operations);
+        }
+        if let Some(v) = &data.web_socket {
+            eprintln!("Connects: {}, connect time (ms): {}", v.connects, v.connect_ms);
             eprintln!(
-                "Thread #{}:\tTotal: {}\tOK: {}\tError: {}\tRequest Error: {}",
-                d.0, d.1.count, d.1.success, d.1.error, d.1.client_error
-            )
+                "Messages sent: {}, received: {}, round trip (ms): {}",
+                v.sent, v.received, v.round_trip_ms
+            );
+            eprintln!(
+                "Disconnects: {}",
+                v.disconnects
+                    .iter()
+                    .map(|v| format!("{} ({})", v.0, v.1))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        if let Some(v) = &data.timing {
+            if let Some(v) = &v.dns {
+                eprintln!("DNS (ms): {}", v);
+            }
+            if let Some(v) = &v.connect {
+                eprintln!("Connect (ms): {}", v);
+            }
+            if let Some(v) = &v.tls {
+                eprintln!("TLS handshake (ms): {}", v);
+            }
+            if let Some(v) = &v.ttfb {
+                eprintln!("Time to first byte (ms): {}", v);
+            }
+            if let Some(v) = &v.transfer {
+                eprintln!("Transfer (ms): {}", v);
+            }
+        }
+        if let Some(v) = &data.stream {
+            eprintln!(
+                "Streams opened: {}, first byte (ms): {}, lifetime (ms): {}",
+                v.opened, v.first_byte_ms, v.lifetime_ms
+            );
+            eprintln!("Events: {}, gap (ms): {}", v.events, v.gap_ms);
+            eprintln!(
+                "Closes: {}",
+                v.closes
+                    .iter()
+                    .map(|v| format!("{} ({})", v.0, v.1))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
         }
     }
 }
@@ -310,3 +1760,513 @@ impl From<QueryValueParser> for QueryValueParserState {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::config::Config,
+        std::io::{
+            BufRead,
+            BufReader,
+            Read,
+            Write,
+        },
+    };
+
+    /// A local HTTP/1.1 server that answers every request with the response
+    /// `respond` builds from its head. Returns the URL of the server.
+    fn http_server(respond: impl Fn(&str) -> String+Send+Sync+'static) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let respond = Arc::new(respond);
+        spawn(move || {
+            for stream in listener.incoming() {
+                let respond = respond.clone();
+                spawn(move || {
+                    let mut stream = stream.unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    loop {
+                        let mut head = String::new();
+                        while !head.ends_with("\r\n\r\n") {
+                            if reader.read_line(&mut head).unwrap_or(0) == 0 {
+                                return;
+                            }
+                        }
+                        let length = head
+                            .lines()
+                            .filter_map(|v| v.split_once(':'))
+                            .find(|v| v.0.eq_ignore_ascii_case("content-length"))
+                            .map_or(0, |v| v.1.trim().parse::<usize>().unwrap());
+                        reader.read_exact(&mut vec![0; length]).unwrap();
+                        if stream.write_all(respond(&head).as_bytes()).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    fn response(status: &str, header: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\n{}content-length: {}\r\n\r\n{}",
+            status,
+            header,
+            body.len(),
+            body
+        )
+    }
+
+    fn worker(status_tx: flume::Sender<(usize, usize, ThreadEvent)>, target: &str) -> Worker {
+        Worker {
+            reporter: Reporter::new(0, 0, status_tx, Default::default()),
+            target: target.to_owned(),
+            client: client::builder(&None, &client::Setup::default(), 0).unwrap().build(),
+            auth: None,
+            session: None,
+            stream: None,
+            tracer: None,
+            recorder: None,
+            on_error: ErrorBehaviour { backoff: None },
+            graphql_errors_fail: true,
+        }
+    }
+
+    fn task(url: &str, operation: Option<&str>) -> Task {
+        Task {
+            method: Method::GET,
+            url: url.to_owned(),
+            header: HeaderMap::new(),
+            query: Vec::new(),
+            body: None,
+            timeout: None,
+            operation: operation.map(|v| v.to_owned()),
+            trace: None,
+        }
+    }
+
+    #[test]
+    fn test_produce() {
+        let ends = End {
+            requests: Some(12),
+            time: None,
+        };
+        let (tasks_tx, tasks_rx) = flume::unbounded();
+        Engine::produce(&ends, tasks_tx, None, |req_idx| {
+            if req_idx >= 10 {
+                return Err(Error::Config("odd number of hex digits".into()).into());
+            }
+            Ok(req_idx)
+        });
+        let tasks = tasks_rx.iter().collect::<Vec<_>>();
+        assert_eq!(tasks.len(), 12);
+        assert!(tasks[..10].iter().all(|v| v.is_ok()));
+        let error = tasks[11].as_ref().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "template request #11: config odd number of hex digits"
+        );
+        assert_eq!(Sample::error("localhost:1".into(), error).class, "template");
+    }
+
+    #[test]
+    fn test_matcher() {
+        let tcp = Spec::Tcp {
+            payloads: Vec::new(),
+            read: None,
+            keep_alive: None,
+        };
+        let bytes = Matcher::new(&tcp, r"^(?-u:\xff)\x00").unwrap();
+        let text = Matcher::new(
+            &Spec::Get {
+                header: HashMap::new(),
+                query: HashMap::new(),
+            },
+            "^2..$",
+        )
+        .unwrap();
+        assert!(matches!(&text, Matcher::Text(v) if v.is_match("200").unwrap()));
+        match bytes {
+            | Matcher::Bytes(v) => {
+                assert!(v.is_match(&[0xff, 0x00, 0xfe][..]));
+                assert!(!v.is_match("\u{ff}\0".as_bytes()));
+            },
+            | Matcher::Text(_) => panic!("expected a bytes matcher"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_phase_groups() {
+        let mut config = serde_yaml::from_str::<Config>(include_str!("../res/example.yaml")).unwrap();
+        let campaign = config.campaigns.get_mut("main").unwrap();
+        let phase = campaign.phases[0].clone();
+        campaign.phases = vec![
+            Phase {
+                group: Some("a".to_owned()),
+                ..phase.clone()
+            },
+            Phase {
+                group: Some("a".to_owned()),
+                ..phase.clone()
+            },
+            phase.clone(),
+            Phase {
+                group: Some("a".to_owned()),
+                ..phase.clone()
+            },
+        ];
+
+        let groups = Engine::phase_groups(campaign)
+            .into_iter()
+            .map(|v| v.into_iter().map(|v| v.0).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(groups, vec![vec![0, 1], vec![2], vec![3]]);
+    }
+
+    /// A campaign of a single phase with the given keys, of a single thread
+    /// that sends 2 requests unless they say otherwise.
+    fn campaign(phase: serde_json::Value) -> Campaign {
+        let mut base = serde_json::json!({
+            "threads": 1, "ends": { "requests": 2 }, "timeout": { "s": 5 }, "report": {},
+            "behaviours": { "ok": [], "error": {} },
+        });
+        base.as_object_mut().unwrap().extend(phase.as_object().unwrap().clone());
+        serde_json::from_value(serde_json::json!({ "phases": [base] })).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_raid_all() {
+        let url = http_server(|_| response("200 OK", "", "ok"));
+        // campaigns differ in their number of requests to tell their results apart
+        let campaigns = [("b", 3), ("a", 2), ("c", 4)]
+            .map(|(name, requests)| {
+                let campaign = campaign(serde_json::json!({
+                    "target": { "static": url },
+                    "spec": { "get": { "header": {}, "query": {} } },
+                    "ends": { "requests": requests },
+                }));
+                (name.to_owned(), campaign)
+            })
+            .to_vec();
+        let engine = Engine {
+            output: Output::Quiet,
+            ..Default::default()
+        };
+        for concurrent in [false, true] {
+            let results = engine.raid_all(campaigns.clone(), concurrent, None).await.unwrap();
+            let counts = results
+                .campaigns
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.phases[0].count))
+                .collect::<Vec<_>>();
+            assert_eq!(counts, vec![("a", 2), ("b", 3), ("c", 4)]);
+            assert_eq!(results.config.keys().collect::<Vec<_>>(), vec!["a", "b", "c"]);
+            assert_eq!(results.config["c"].phases[0].ends.requests, Some(4));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_raid_all_failure() {
+        let served = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = {
+            let served = served.clone();
+            http_server(move |_| {
+                served.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                response("200 OK", "", "ok")
+            })
+        };
+        // the targets of b and d are read from environment variables that are not set
+        let campaigns = [
+            ("a", serde_json::json!({ "static": url })),
+            ("b", serde_json::json!({ "env": "VIKING_TEST_UNSET_B" })),
+            ("c", serde_json::json!({ "static": url })),
+            ("d", serde_json::json!({ "env": "VIKING_TEST_UNSET_D" })),
+        ]
+        .map(|(name, target)| {
+            let campaign = campaign(serde_json::json!({
+                "target": target,
+                "spec": { "get": { "header": {}, "query": {} } },
+                "ends": { "requests": 2 },
+            }));
+            (name.to_owned(), campaign)
+        })
+        .to_vec();
+        let engine = Engine {
+            output: Output::Quiet,
+            ..Default::default()
+        };
+        for concurrent in [false, true] {
+            served.store(0, std::sync::atomic::Ordering::SeqCst);
+            let err = engine
+                .raid_all(campaigns.clone(), concurrent, None)
+                .await
+                .unwrap_err()
+                .to_string();
+            assert!(err.starts_with("campaign failed: b: "), "{}", err);
+            assert!(err.contains("VIKING_TEST_UNSET_B"), "{}", err);
+            assert!(err.contains("; d: "), "{}", err);
+            assert!(!err.contains("; a: ") && !err.contains("; c: "), "{}", err);
+            // the campaigns after the failed ones still ran
+            assert_eq!(served.load(std::sync::atomic::Ordering::SeqCst), 4);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_message_samples() {
+        let url = http_server(|_| response("200 OK", "", "pong"));
+        let campaign = campaign(serde_json::json!({
+            "target": { "static": url.trim_start_matches("http://") },
+            "spec": { "tcp": {
+                "payloads": [{ "text": "GET / HTTP/1.1\r\nhost: localhost\r\n\r\n" }],
+                "read": { "delimiter": "\r\n" },
+                "keep_alive": false,
+            } },
+            "behaviours": { "ok": [{ "match": "^HTTP/1.1 200", "mark": "error" }], "error": {} },
+        }));
+        let engine = Engine {
+            output: Output::Quiet,
+            ..Default::default()
+        };
+        let results = engine.raid("main", &campaign, None).await.unwrap();
+        let phase = &results.phases[0];
+        assert_eq!(phase.error, 2);
+        // responses of TCP phases are sampled once the behaviours mark them
+        let failures = &phase.failures["HTTP/1.1 200 OK\r\n"];
+        assert_eq!((failures.count, failures.samples.len()), (2, 2));
+        let sample = &failures.samples[0];
+        assert_eq!(sample.request, url.trim_start_matches("http://"));
+        assert_eq!(
+            (sample.status.as_deref(), sample.message.as_str()),
+            (None, "HTTP/1.1 200 OK\r\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unrendered() {
+        let url = http_server(|_| response("200 OK", "", "pong"));
+        // only the first payload has an even number of hex digits, the others are
+        // never sent
+        let campaign = campaign(serde_json::json!({
+            "target": { "static": url.trim_start_matches("http://") },
+            "spec": { "tcp": {
+                "payloads": [{ "hex": "{{#if sequence}}0{{/if}}0d0a0d0a" }],
+                "read": { "delimiter": "\r\n" },
+            } },
+            "ends": { "requests": 3 },
+        }));
+        let engine = Engine {
+            output: Output::Quiet,
+            ..Default::default()
+        };
+        let results = engine.raid("main", &campaign, None).await.unwrap();
+        let phase = &results.phases[0];
+        assert_eq!((phase.count, phase.client_error), (3, 2));
+        assert_eq!(phase.failures["template"].count, 2);
+        // requests that were never sent have no latency
+        assert_eq!(phase.latency.as_ref().unwrap().count, 1);
+        let series = &phase.series[0];
+        assert_eq!((series.requests, series.errors), (3, 2));
+    }
+
+    #[test]
+    fn test_session() {
+        // the login sets a new session cookie every time, other requests are recorded
+        let logins = Arc::new(AtomicUsize::new(0));
+        let requests = Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+        let url = {
+            let (logins, requests) = (logins.clone(), requests.clone());
+            http_server(move |head| {
+                let cookie = head
+                    .lines()
+                    .find_map(|v| v.strip_prefix("cookie: "))
+                    .unwrap_or("-")
+                    .to_owned();
+                if head.starts_with("POST /login ") {
+                    requests.lock().unwrap().push(format!("login {}", cookie));
+                    let session = logins.fetch_add(1, Ordering::Relaxed) + 1;
+                    response("204 No Content", &format!("set-cookie: session={}\r\n", session), "")
+                } else {
+                    requests.lock().unwrap().push(cookie);
+                    response("200 OK", "", "ok")
+                }
+            })
+        };
+        let run = |reset_every: Option<usize>, count: usize| {
+            let (status_tx, status_rx) = flume::unbounded();
+            let mut worker = worker(status_tx, &url);
+            let cookies = Arc::new(CookieJar::default());
+            worker.client = client::builder(&None, &client::Setup::default(), 0)
+                .unwrap()
+                .cookie_provider(cookies.clone())
+                .build();
+            worker.session = Some(WorkerSession {
+                cookies,
+                login: Some(Arc::new(Login {
+                    method: Method::POST,
+                    target: format!("{}/login", url),
+                    header: HeaderMap::new(),
+                    form: vec![("user".to_owned(), "viking".to_owned())],
+                })),
+                reset_every,
+                requests: 0,
+                active: false,
+            });
+            let (tasks_tx, tasks_rx) = flume::unbounded();
+            for _ in 0..count {
+                tasks_tx.send(Ok(task(&format!("{}/", url), None))).unwrap();
+            }
+            drop(tasks_tx);
+            worker.run(tasks_rx);
+            status_rx
+                .iter()
+                .filter(|v| matches!(v.2, ThreadEvent::Success { .. }))
+                .count()
+        };
+
+        // the cookie of the login is sent with every following request
+        assert_eq!(run(None, 3), 3);
+        assert_eq!(std::mem::take(&mut *requests.lock().unwrap()), vec![
+            "login -",
+            "session=1",
+            "session=1",
+            "session=1"
+        ]);
+        // a reset drops the cookie before logging in again
+        assert_eq!(run(Some(2), 5), 5);
+        assert_eq!(std::mem::take(&mut *requests.lock().unwrap()), vec![
+            "login -",
+            "session=2",
+            "session=2",
+            "login -",
+            "session=3",
+            "session=3",
+            "login -",
+            "session=4",
+        ]);
+    }
+
+    #[test]
+    fn test_output() {
+        for (name, output) in [
+            ("tty", Output::Tty),
+            ("plain", Output::Plain),
+            ("json", Output::Json),
+            ("quiet", Output::Quiet),
+        ] {
+            assert_eq!(Output::from_name(name).unwrap(), output);
+        }
+        assert!(Output::from_name("xml").is_err());
+        if !std::io::stdout().is_terminal() {
+            assert_eq!(Output::detect(), Output::Plain);
+        }
+    }
+
+    #[test]
+    fn test_report_lines() {
+        let mut data: PhaseResults = serde_json::from_value(serde_json::json!({
+            "elapsed_ms": 2500,
+            "count": 10,
+            "success": 7,
+            "error": 2,
+            "client_error": 1,
+            "statuses": { "200": 7, "500": 2 },
+        }))
+        .unwrap();
+        assert_eq!(
+            plain_line("main", 1, &data),
+            "[2.5s] Campaign \"main\", phase #1:\tTotal: 10\tOK: 7\tError: 2\tRequest Error: 1\t4.00 requests / second"
+        );
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json_line("main", 1, data.clone())).unwrap(),
+            serde_json::json!({
+                "campaign": "main",
+                "phase": 1,
+                "elapsed_ms": 2500,
+                "count": 10,
+                "success": 7,
+                "error": 2,
+                "client_error": 1,
+                "statuses": { "200": 7, "500": 2 },
+            })
+        );
+
+        data.latency = Some(crate::results::LatencyResults {
+            p50_ms: 12.0,
+            p99_ms: 80.5,
+            ..Default::default()
+        });
+        assert!(plain_line("main", 1, &data).ends_with("4.00 requests / second\tp50: 12.00 ms\tp99: 80.50 ms"));
+        assert!(!json_line("main", 1, data).contains('\n'));
+    }
+
+    #[test]
+    fn test_graphql_errors() {
+        let url = http_server(|_| response("200 OK", "", r#"{"data":null,"errors":[{"message":"no user"}]}"#));
+        let (status_tx, _status_rx) = flume::unbounded();
+        let mut worker = worker(status_tx, &url);
+        let start = std::time::Instant::now();
+        match worker.exchange(task(&url, Some("GetUser")), "POST", start).unwrap() {
+            | ThreadEvent::Failure { sample, .. } => assert_eq!(sample.unwrap().class, "graphql errors"),
+            | v => panic!("expected a failure, got {:?}", v),
+        }
+        // other specs do not look into the body
+        assert!(matches!(
+            worker.exchange(task(&url, None), "GET", start).unwrap(),
+            ThreadEvent::Success { .. }
+        ));
+
+        worker.graphql_errors_fail = false;
+        match worker.exchange(task(&url, Some("GetUser")), "POST", start).unwrap() {
+            | ThreadEvent::Success { status, sample, .. } => {
+                assert_eq!((status.as_str(), sample.is_none()), ("200", true))
+            },
+            | v => panic!("expected a success, got {:?}", v),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_operations() {
+        let spec = Spec::GraphQl {
+            header: None,
+            operations: Vec::new(),
+            errors_fail: None,
+        };
+        let mut a = ThreadStats::new(&spec);
+        let ms = Duration::from_millis;
+        a.record(Some(Outcome::Success), Some("GetUser".to_owned()), Some("200"), ms(10));
+        a.record(Some(Outcome::Error), Some("Broken".to_owned()), None, ms(20));
+        let mut b = ThreadStats::new(&spec);
+        b.record(Some(Outcome::Success), Some("GetUser".to_owned()), Some("200"), ms(30));
+        b.record(None, Some("GetUser".to_owned()), Some("404"), ms(40));
+        b.record(Some(Outcome::ClientError), None, None, ms(50));
+
+        b.finished = Some(ms(500));
+        let stats = BTreeMap::from([(0, a), (1, b)]);
+
+        let results = ThreadStats::aggregate(&stats, Duration::from_secs(1));
+        assert_eq!(results.count, 5);
+        assert_eq!(results.client_error, 1);
+        assert_eq!(
+            results.statuses,
+            BTreeMap::from([("200".to_owned(), 2), ("404".to_owned(), 1)])
+        );
+        let latency = results.latency.unwrap();
+        assert_eq!((latency.count, latency.min_ms, latency.max_ms), (5, 10.0, 50.0));
+        let get_user = results.operations.get("GetUser").unwrap();
+        assert_eq!((get_user.count, get_user.success, get_user.error), (3, 2, 0));
+        let broken = results.operations.get("Broken").unwrap();
+        assert_eq!((broken.count, broken.success, broken.error), (1, 0, 1));
+
+        let threads = ThreadStats::threads(&stats, Duration::from_secs(1));
+        assert_eq!(threads.len(), 2);
+        assert_eq!(
+            (threads[1].thread, threads[1].count, threads[1].client_error),
+            (1, 3, 1)
+        );
+        assert!((threads[0].busy_ratio - 0.03).abs() < 1e-9);
+        // the second thread finished half way through the phase
+        assert!((threads[1].busy_ratio - 0.24).abs() < 1e-9);
+    }
+}
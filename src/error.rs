@@ -4,6 +4,24 @@ pub(crate) enum Error {
     Argument(String),
     #[error("unknown command")]
     UnknownCommand,
+    #[error("auth {0}")]
+    Auth(String),
+    #[error("config {0}")]
+    Config(String),
     #[error("version compatibility {0}")]
     VersionCompatibility(String),
+    #[error("template {0}")]
+    Template(String),
+    #[error("timeout {0}")]
+    Timeout(String),
+    #[error("connect {0}")]
+    Connect(String),
+    #[error("http {0}")]
+    Http(String),
+    #[error("websocket {0}")]
+    WebSocket(String),
+    #[error("campaign {0}")]
+    Campaign(String),
+    #[error("regression {0}")]
+    Regression(String),
 }
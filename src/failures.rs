@@ -0,0 +1,228 @@
+use {
+    crate::{
+        error::Error,
+        redact,
+    },
+    rand::Rng,
+    reqwest::header::HeaderMap,
+    std::collections::BTreeMap,
+};
+
+/// Samples kept per class of failures.
+const SAMPLES: usize = 5;
+/// Classes kept per phase. Failures of further classes are counted as
+/// `OTHER`, e.g. when the status of a TCP phase is the response itself.
+const CLASSES: usize = 20;
+const OTHER: &str = "other";
+/// Length of the class names, in characters.
+const CLASS_LENGTH: usize = 64;
+/// Length of the response body kept in a sample, in bytes.
+const BODY_LENGTH: usize = 1024;
+
+/// A failed request, as kept in the samples of its class.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Sample {
+    /// Status code or kind of error, the key of the class.
+    #[serde(skip)]
+    pub class: String,
+    /// Method and URL of the request, or the target for other specs.
+    pub request: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Start of the response body, or the error message.
+    pub message: String,
+}
+
+impl Sample {
+    /// A response with its headers and the start of its body. Values of
+    /// sensitive headers (e.g. `set-cookie`) are redacted.
+    pub fn response(request: String, status: &str, header: &HeaderMap, body: &[u8]) -> Self {
+        let mut headers = BTreeMap::<String, String>::new();
+        for (k, v) in header {
+            let v = if redact::is_sensitive(k.as_str()) {
+                redact::REDACTED.into()
+            } else {
+                String::from_utf8_lossy(v.as_bytes())
+            };
+            headers
+                .entry(k.to_string())
+                .and_modify(|e| {
+                    e.push_str(", ");
+                    e.push_str(&v);
+                })
+                .or_insert_with(|| v.into_owned());
+        }
+        Self {
+            class: status.to_owned(),
+            request: redact_request(&request),
+            status: Some(status.to_owned()),
+            headers,
+            message: body_start(body),
+        }
+    }
+
+    /// A response without headers: a gRPC status with its message, or a
+    /// message of a TCP, UDP or WebSocket phase. Those have no `status` apart
+    /// from the message, which is the `class` then.
+    pub fn message(request: String, status: Option<&str>, class: &str, body: &[u8]) -> Self {
+        Self {
+            class: class.to_owned(),
+            request: redact_request(&request),
+            status: status.map(|v| v.to_owned()),
+            headers: BTreeMap::new(),
+            message: body_start(body),
+        }
+    }
+
+    /// A request that got no response.
+    pub fn error(request: String, error: &anyhow::Error) -> Self {
+        let mut message = format!("{:#}", error);
+        // errors of the HTTP client name the URL of the request
+        if let Some(url) = error.downcast_ref::<reqwest::Error>().and_then(|v| v.url()) {
+            message = message.replace(url.as_str(), &redact::url(url.as_str()));
+        }
+        Self {
+            class: error_class(error).to_owned(),
+            request: redact_request(&request),
+            status: None,
+            headers: BTreeMap::new(),
+            message,
+        }
+    }
+}
+
+/// Redacts the URL of a request like `GET http://localhost/?token=...`.
+fn redact_request(request: &str) -> String {
+    match request.split_once(' ') {
+        | Some((method, url)) => format!("{} {}", method, redact::url(url)),
+        | None => redact::url(request),
+    }
+}
+
+/// The start of a response body, as text.
+fn body_start(body: &[u8]) -> String {
+    let mut message = String::from_utf8_lossy(&body[..body.len().min(BODY_LENGTH)]).into_owned();
+    if body.len() > BODY_LENGTH {
+        message.push('…');
+    }
+    message
+}
+
+/// Failures of a class and some of them as samples.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct FailureResults {
+    pub count: usize,
+    pub samples: Vec<Sample>,
+}
+
+/// Failures of a phase by class, with a bounded number of samples each. The
+/// samples are a uniform choice among all samples of their class.
+#[derive(Debug, Default)]
+pub struct Reservoir {
+    classes: BTreeMap<String, (FailureResults, usize)>,
+}
+
+impl Reservoir {
+    pub fn add(&mut self, class: &str, sample: Option<Sample>) {
+        let class = if self.classes.contains_key(class) || self.classes.len() < CLASSES {
+            class.chars().take(CLASS_LENGTH).collect::<String>()
+        } else {
+            OTHER.to_owned()
+        };
+        let (results, offered) = self.classes.entry(class).or_default();
+        results.count += 1;
+        let sample = match sample {
+            | Some(v) => v,
+            | None => return,
+        };
+        *offered += 1;
+        if results.samples.len() < SAMPLES {
+            results.samples.push(sample);
+        } else {
+            let idx = rand::thread_rng().gen_range(0..*offered);
+            if idx < SAMPLES {
+                results.samples[idx] = sample;
+            }
+        }
+    }
+
+    pub fn results(&self) -> BTreeMap<String, FailureResults> {
+        self.classes.iter().map(|(k, v)| (k.clone(), v.0.clone())).collect()
+    }
+}
+
+/// Groups requests without a response by what went wrong.
+fn error_class(error: &anyhow::Error) -> &'static str {
+    match error.downcast_ref::<Error>() {
+        | Some(Error::Template(_)) => return "template",
+        | Some(Error::Timeout(_)) => return "timeout",
+        | Some(Error::Connect(_)) => return "connect",
+        | _ => {},
+    }
+    if let Some(v) = error.downcast_ref::<reqwest::Error>() {
+        if v.is_timeout() {
+            return "timeout";
+        }
+        if v.is_connect() {
+            return "connect";
+        }
+    }
+    if let Some(v) = error.downcast_ref::<std::io::Error>() {
+        match v.kind() {
+            | std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => return "timeout",
+            | std::io::ErrorKind::ConnectionRefused => return "connect",
+            | _ => {},
+        }
+    }
+    "request error"
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reservoir() {
+        let mut reservoir = Reservoir::default();
+        let mut header = HeaderMap::new();
+        header.append("x-a", "1".parse().unwrap());
+        header.append("x-a", "2".parse().unwrap());
+        header.append("set-cookie", "session=hunter2".parse().unwrap());
+        for idx in 0..100 {
+            let body = format!("{}{}", idx, "x".repeat(2000));
+            let request = "GET http://localhost/?page=1&token=hunter2".into();
+            let sample = Sample::response(request, "500", &header, body.as_bytes());
+            reservoir.add("500", Some(sample));
+        }
+        reservoir.add("500", None);
+        let error = anyhow::Error::from(std::io::Error::from(std::io::ErrorKind::TimedOut));
+        let sample = Sample::error("localhost:1".into(), &error);
+        reservoir.add(&sample.class.clone(), Some(sample));
+        let sample = Sample::message("echo.Echo/Say".into(), Some("5"), "5", b"no such user");
+        reservoir.add(&sample.class.clone(), Some(sample));
+        for idx in 0..CLASSES - 1 {
+            reservoir.add(&format!("message {}", idx), None);
+        }
+
+        let results = reservoir.results();
+        let server = &results["500"];
+        assert_eq!(server.count, 101);
+        assert_eq!(server.samples.len(), SAMPLES);
+        assert_eq!(server.samples[0].headers["x-a"], "1, 2");
+        assert_eq!(server.samples[0].headers["set-cookie"], redact::REDACTED);
+        assert_eq!(server.samples[0].request, "GET http://localhost/?page=1&token=***");
+        assert!(server
+            .samples
+            .iter()
+            .all(|v| v.message.len() == BODY_LENGTH + '…'.len_utf8()));
+        assert_eq!(results["timeout"].count, 1);
+        assert_eq!(results["timeout"].samples[0].status, None);
+        assert_eq!(results["5"].samples[0].message, "no such user");
+        assert_eq!(results.len(), CLASSES + 1);
+        assert_eq!(results[OTHER].count, 2);
+    }
+}
@@ -0,0 +1,477 @@
+use {
+    crate::{
+        config::ErrorBehaviour,
+        engine::{
+            Reporter,
+            ThreadEvent,
+        },
+        error::Error,
+        failures::Sample,
+    },
+    anyhow::Result,
+    prost::Message,
+    prost_reflect::{
+        DescriptorPool,
+        DynamicMessage,
+        MessageDescriptor,
+    },
+    reqwest::header::HeaderMap,
+    std::{
+        collections::BTreeMap,
+        path::Path,
+        sync::Arc,
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+    tonic::{
+        codec::{
+            Codec,
+            DecodeBuf,
+            Decoder,
+            EncodeBuf,
+            Encoder,
+        },
+        codegen::{
+            http::uri::PathAndQuery,
+            tokio_stream,
+        },
+        metadata::MetadataMap,
+        transport::{
+            Channel,
+            Endpoint,
+        },
+        Code,
+        Request,
+        Status,
+    },
+    tonic_reflection::pb::v1::{
+        server_reflection_client::ServerReflectionClient,
+        server_reflection_request::MessageRequest,
+        server_reflection_response::MessageResponse,
+        ServerReflectionRequest,
+    },
+};
+
+/// Loads the descriptors of a `.proto` file and everything it imports.
+/// Imports are looked up in `includes`, or next to the file if there are none.
+pub fn load_proto(path: &Path, includes: &[String]) -> Result<DescriptorPool> {
+    let mut parser = protobuf_parse::Parser::new();
+    parser.pure().input(path);
+    if includes.is_empty() {
+        parser.include(path.parent().unwrap_or(Path::new(".")));
+    }
+    parser.includes(includes);
+    let set = parser
+        .file_descriptor_set()
+        .map_err(|e| Error::Config(format!("can not load {}: {:#}", path.display(), e)))?;
+    Ok(DescriptorPool::decode(
+        protobuf::Message::write_to_bytes(&set)?.as_slice(),
+    )?)
+}
+
+/// Fetches the descriptors that define `service` (and their dependencies)
+/// from the server reflection service of `target`.
+pub async fn reflect(target: &str, service: &str) -> Result<DescriptorPool> {
+    let mut client = ServerReflectionClient::new(Endpoint::from_shared(target.to_owned())?.connect().await?);
+    let mut files = BTreeMap::<String, prost_types::FileDescriptorProto>::new();
+    let mut pending = vec![MessageRequest::FileContainingSymbol(service.to_owned())];
+    while let Some(request) = pending.pop() {
+        let request = ServerReflectionRequest {
+            host: "".to_owned(),
+            message_request: Some(request),
+        };
+        let response = client
+            .server_reflection_info(tokio_stream::once(request))
+            .await?
+            .into_inner()
+            .message()
+            .await?
+            .and_then(|v| v.message_response);
+        let descriptors = match response {
+            | Some(MessageResponse::FileDescriptorResponse(v)) => v.file_descriptor_proto,
+            | Some(MessageResponse::ErrorResponse(v)) => {
+                return Err(Error::Config(format!("reflection of {} failed: {}", service, v.error_message)).into())
+            },
+            | _ => return Err(Error::Config(format!("reflection of {} failed", service)).into()),
+        };
+        for descriptor in descriptors {
+            let file = prost_types::FileDescriptorProto::decode(descriptor.as_slice())?;
+            for dependency in &file.dependency {
+                if !files.contains_key(dependency) {
+                    pending.push(MessageRequest::FileByFilename(dependency.clone()));
+                }
+            }
+            files.insert(file.name().to_owned(), file);
+        }
+    }
+
+    let mut pool = DescriptorPool::new();
+    pool.add_file_descriptor_protos(files.into_values())?;
+    Ok(pool)
+}
+
+/// The gRPC method of a phase, resolved once per phase.
+pub struct Method {
+    pub target: Endpoint,
+    pub path: PathAndQuery,
+    pub input: MessageDescriptor,
+    pub output: MessageDescriptor,
+    pub server_streaming: bool,
+    pub metadata: HeaderMap,
+    pub timeout: Duration,
+}
+
+impl Method {
+    /// Looks up `name` (`package.Service/Method`) in the descriptors.
+    pub fn new(
+        pool: &DescriptorPool,
+        target: &str,
+        name: &str,
+        metadata: HeaderMap,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let (service, method) = name.split_once('/').ok_or_else(|| {
+            Error::Config(format!(
+                "grpc method {} is not of the form package.Service/Method",
+                name
+            ))
+        })?;
+        let method = pool
+            .get_service_by_name(service)
+            .and_then(|v| v.methods().find(|v| v.name() == method))
+            .ok_or_else(|| Error::Config(format!("grpc method {} is not defined", name)))?;
+        if method.is_client_streaming() {
+            return Err(Error::Config(format!(
+                "grpc method {} is client streaming, which is not supported",
+                name
+            ))
+            .into());
+        }
+        Ok(Self {
+            target: Endpoint::from_shared(target.to_owned())?,
+            path: format!("/{}/{}", service, method.name()).parse()?,
+            input: method.input(),
+            output: method.output(),
+            server_streaming: method.is_server_streaming(),
+            metadata,
+            timeout,
+        })
+    }
+
+    /// Builds a request message from its JSON representation.
+    pub fn message(&self, json: &str) -> Result<DynamicMessage> {
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        let message = DynamicMessage::deserialize(self.input.clone(), &mut deserializer)
+            .map_err(|e| Error::Config(format!("invalid {} message: {}", self.input.full_name(), e)))?;
+        deserializer.end()?;
+        Ok(message)
+    }
+}
+
+/// Encodes and decodes messages described by descriptors.
+#[derive(Clone)]
+struct DynamicCodec(MessageDescriptor);
+
+impl Codec for DynamicCodec {
+    type Decode = DynamicMessage;
+    type Decoder = Self;
+    type Encode = DynamicMessage;
+    type Encoder = Self;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        self.clone()
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        self.clone()
+    }
+}
+
+impl Encoder for DynamicCodec {
+    type Error = Status;
+    type Item = DynamicMessage;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
+        item.encode(dst).map_err(|e| Status::internal(e.to_string()))
+    }
+}
+
+impl Decoder for DynamicCodec {
+    type Error = Status;
+    type Item = DynamicMessage;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
+        DynamicMessage::decode(self.0.clone(), src)
+            .map(Some)
+            .map_err(|e| Status::internal(e.to_string()))
+    }
+}
+
+/// A worker thread (virtual user) of a gRPC phase. Every worker has its own
+/// connection, driven by a single threaded runtime.
+pub(crate) struct Worker {
+    pub reporter: Reporter,
+    pub method: Arc<Method>,
+    pub on_error: ErrorBehaviour,
+}
+
+impl Worker {
+    pub fn run(self, tasks: flume::Receiver<Result<DynamicMessage>>) {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let channel = {
+            let _guard = runtime.enter();
+            self.method.target.connect_lazy()
+        };
+        for message in tasks.iter() {
+            self.reporter.dequeued();
+            let message = match message {
+                | Ok(v) => v,
+                | Err(e) => {
+                    self.reporter.unrendered(self.method.path.to_string(), &e);
+                    continue;
+                },
+            };
+            let start = Instant::now();
+            match runtime.block_on(self.call(channel.clone(), message)) {
+                | Ok(status) => {
+                    let code = (status.code() as i32).to_string();
+                    let sample = (status.code() != Code::Ok).then(|| {
+                        let path = self.method.path.to_string();
+                        Box::new(Sample::message(path, Some(&code), &code, status.message().as_bytes()))
+                    });
+                    self.reporter.report(ThreadEvent::Success {
+                        status: code,
+                        response: None,
+                        operation: None,
+                        latency: start.elapsed(),
+                        sample,
+                    })
+                },
+                | Err(e) => {
+                    self.reporter.report(ThreadEvent::Error {
+                        operation: None,
+                        latency: start.elapsed(),
+                        sample: Some(Box::new(Sample::error(self.method.path.to_string(), &e))),
+                    });
+                    if let Some(v) = &self.on_error.backoff {
+                        std::thread::sleep(Duration::from_millis(v.to_ms()));
+                    }
+                },
+            }
+        }
+        self.reporter.report(ThreadEvent::Finished);
+    }
+
+    /// Calls the method and returns the status of the call. Fails if the call
+    /// did not reach the server or timed out.
+    async fn call(&self, channel: Channel, message: DynamicMessage) -> Result<Status> {
+        let method = &self.method;
+        let mut request = Request::new(message);
+        *request.metadata_mut() = MetadataMap::from_headers(method.metadata.clone());
+        request.set_timeout(method.timeout);
+
+        let codec = DynamicCodec(method.output.clone());
+        let mut grpc = tonic::client::Grpc::new(channel);
+        let call = async {
+            grpc.ready().await.map_err(|e| Status::unavailable(e.to_string()))?;
+            if method.server_streaming {
+                let mut stream = grpc
+                    .server_streaming(request, method.path.clone(), codec)
+                    .await?
+                    .into_inner();
+                while stream.message().await?.is_some() {}
+            } else {
+                grpc.unary(request, method.path.clone(), codec).await?;
+            }
+            Ok::<_, Status>(())
+        };
+        match tokio::time::timeout(method.timeout, call).await {
+            | Ok(Ok(_)) => Ok(Status::ok("")),
+            // statuses with a source were not sent by the server
+            | Ok(Err(e)) if std::error::Error::source(&e).is_some() => Err(e.into()),
+            | Ok(Err(e)) => Ok(e),
+            | Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        tonic::{
+            codegen::{
+                http,
+                BoxFuture,
+                Service,
+            },
+            server::{
+                NamedService,
+                ServerStreamingService,
+                UnaryService,
+            },
+            Response,
+        },
+    };
+
+    const PROTO: &str = r#"
+syntax = "proto3";
+package echo;
+message Message {
+  string text = 1;
+  int64 sequence = 2;
+}
+service Echo {
+  rpc Say(Message) returns (Message);
+  rpc Repeat(Message) returns (stream Message);
+}
+"#;
+
+    /// Echoes the request, or responds with NOT_FOUND if its text is "fail".
+    struct Say;
+
+    impl UnaryService<DynamicMessage> for Say {
+        type Future = std::future::Ready<Result<Response<DynamicMessage>, Status>>;
+        type Response = DynamicMessage;
+
+        fn call(&mut self, request: Request<DynamicMessage>) -> Self::Future {
+            let message = request.into_inner();
+            std::future::ready(match message.get_field_by_name("text").unwrap().as_str() {
+                | Some("fail") => Err(Status::not_found("fail")),
+                | _ => Ok(Response::new(message)),
+            })
+        }
+    }
+
+    /// Streams the request back three times.
+    struct Repeat;
+
+    impl ServerStreamingService<DynamicMessage> for Repeat {
+        type Future = std::future::Ready<Result<Response<Self::ResponseStream>, Status>>;
+        type Response = DynamicMessage;
+        type ResponseStream = tokio_stream::Iter<std::vec::IntoIter<Result<DynamicMessage, Status>>>;
+
+        fn call(&mut self, request: Request<DynamicMessage>) -> Self::Future {
+            let message = request.into_inner();
+            std::future::ready(Ok(Response::new(tokio_stream::iter(vec![Ok(message); 3]))))
+        }
+    }
+
+    #[derive(Clone)]
+    struct Echo(MessageDescriptor);
+
+    impl NamedService for Echo {
+        const NAME: &'static str = "echo.Echo";
+    }
+
+    impl Service<http::Request<tonic::body::Body>> for Echo {
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        type Response = http::Response<tonic::body::Body>;
+
+        fn poll_ready(&mut self, _: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<tonic::body::Body>) -> Self::Future {
+            let mut grpc = tonic::server::Grpc::new(DynamicCodec(self.0.clone()));
+            Box::pin(async move {
+                Ok(match request.uri().path() {
+                    | "/echo.Echo/Say" => grpc.unary(Say, request).await,
+                    | _ => grpc.server_streaming(Repeat, request).await,
+                })
+            })
+        }
+    }
+
+    /// Serves the echo service and server reflection on a local port.
+    async fn serve(pool: &DescriptorPool) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = format!("http://{}", listener.local_addr().unwrap());
+        let reflection = tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(Box::leak(pool.encode_to_vec().into_boxed_slice()))
+            .build_v1()
+            .unwrap();
+        let echo = Echo(pool.get_message_by_name("echo.Message").unwrap());
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(echo)
+                .add_service(reflection)
+                .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+        );
+        target
+    }
+
+    #[tokio::test]
+    async fn test_grpc() {
+        let dir = std::env::temp_dir().join(format!("viking-grpc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("echo.proto"), PROTO).unwrap();
+        let pool = load_proto(&dir.join("echo.proto"), &[]).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let target = serve(&pool).await;
+        let pool = reflect(&target, "echo.Echo").await.unwrap();
+        let (status_tx, _status_rx) = flume::unbounded();
+        let worker = |name: &str, target: &str| {
+            Worker {
+                reporter: Reporter::new(0, 0, status_tx.clone(), Default::default()),
+                method: Arc::new(Method::new(&pool, target, name, HeaderMap::new(), Duration::from_secs(5)).unwrap()),
+                on_error: ErrorBehaviour { backoff: None },
+            }
+        };
+        async fn call(worker: &Worker, json: &str) -> Result<Code> {
+            let channel = worker.method.target.connect_lazy();
+            Ok(worker.call(channel, worker.method.message(json)?).await?.code())
+        }
+
+        let say = worker("echo.Echo/Say", &target);
+        assert_eq!(
+            call(&say, r#"{"text": "hello", "sequence": 1}"#).await.unwrap(),
+            Code::Ok
+        );
+        assert_eq!(call(&say, r#"{"text": "fail"}"#).await.unwrap(), Code::NotFound);
+
+        // statuses other than OK are sampled with their message
+        let (run_tx, run_rx) = flume::unbounded();
+        let run = Worker {
+            reporter: Reporter::new(0, 0, run_tx, Default::default()),
+            ..worker("echo.Echo/Say", &target)
+        };
+        let (tasks_tx, tasks_rx) = flume::unbounded();
+        tasks_tx.send(run.method.message(r#"{"text": "fail"}"#)).unwrap();
+        drop(tasks_tx);
+        tokio::task::spawn_blocking(move || run.run(tasks_rx)).await.unwrap();
+        match run_rx.recv().unwrap().2 {
+            | ThreadEvent::Success { status, sample, .. } => {
+                let sample = sample.unwrap();
+                assert_eq!((status.as_str(), sample.status.as_deref()), ("5", Some("5")));
+                assert_eq!(
+                    (sample.request.as_str(), sample.message.as_str()),
+                    ("/echo.Echo/Say", "fail")
+                );
+            },
+            | v => panic!("expected a response, got {:?}", v),
+        }
+        assert!(say.method.message(r#"{"unknown": 1}"#).is_err());
+        let repeat = worker("echo.Echo/Repeat", &target);
+        assert!(repeat.method.server_streaming);
+        assert_eq!(call(&repeat, r#"{"text": "hello"}"#).await.unwrap(), Code::Ok);
+        // nothing listens on port 9 (discard)
+        let unreachable = worker("echo.Echo/Say", "http://127.0.0.1:9");
+        assert!(call(&unreachable, "{}").await.is_err());
+        assert!(Method::new(
+            &pool,
+            &target,
+            "echo.Echo/Missing",
+            HeaderMap::new(),
+            Duration::from_secs(1)
+        )
+        .is_err());
+    }
+}
@@ -4,8 +4,10 @@ use {
         Command,
     },
     engine::Engine,
+    metrics::Metrics,
     std::{
         io::Write,
+        sync::Arc,
         thread::spawn,
     },
 };
@@ -13,10 +15,29 @@ use {
 include!("check_features.rs");
 
 mod args;
+mod auth;
+mod client;
+mod compare;
+mod compose;
 mod config;
 mod engine;
 mod error;
+mod failures;
+mod grpc;
+mod metrics;
+mod migrate;
+mod overrides;
+mod push;
+mod redact;
 mod reference;
+mod report;
+mod results;
+mod socket;
+mod stream;
+mod template;
+mod timeseries;
+mod trace;
+mod websocket;
 
 use {
     anyhow::Result,
@@ -52,9 +73,83 @@ async fn main() -> Result<()> {
             println!("{}", include_str!("../res/example.yaml"));
             Ok(())
         },
-        | Command::Raid { config, campaign, loot } => {
-            let engine = Engine {};
-            match loot {
+        | Command::Migrate { file, out } => {
+            let mut doc = serde_yaml::from_str::<serde_yaml::Value>(&std::fs::read_to_string(file)?)?;
+            let changes = migrate::migrate(&mut doc)?;
+            let migrated = serde_yaml::to_string(&doc)?;
+            match out {
+                | Some(v) => std::fs::write(v, migrated)?,
+                | None => print!("{}", migrated),
+            }
+            eprint!("{}", changes);
+            Ok(())
+        },
+        | Command::Compare {
+            baseline,
+            current,
+            tolerances,
+        } => {
+            let load = |path: &str| -> Result<results::RaidResults> {
+                Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+            };
+            let comparison = compare::Comparison::new(&load(&baseline)?, &load(&current)?, &tolerances);
+            print!("{}", comparison);
+            match comparison.regressions() {
+                | 0 => Ok(()),
+                | v => Err(error::Error::Regression(format!("{} metric(s) beyond tolerance or missing", v)).into()),
+            }
+        },
+        | Command::Report { input, out } => {
+            let raid = serde_json::from_str::<results::RaidResults>(&std::fs::read_to_string(input)?)?;
+            std::fs::write(out, report::html(&raid)?)?;
+            Ok(())
+        },
+        | Command::Raid {
+            config,
+            campaigns,
+            concurrent,
+            loot,
+            metrics_listen,
+            statsd,
+            otlp,
+            metrics_prefix,
+            timeseries,
+            results,
+            html,
+            output,
+        } => {
+            let mut engine = Engine {
+                output,
+                ..Engine::default()
+            };
+            if let Some(path) = timeseries {
+                engine.timeseries = Some(Arc::new(timeseries::Writer::create(&path)?));
+            }
+            let mut pushers = Vec::new();
+            let (stop_tx, stop_rx) = flume::bounded::<()>(0);
+            if metrics_listen.is_some() || statsd.is_some() || otlp.is_some() {
+                let metrics = Arc::new(Metrics::new(statsd.is_some()));
+                if let Some(addr) = metrics_listen {
+                    metrics::serve(metrics.clone(), addr).await?;
+                }
+                let targets = statsd
+                    .map(push::Target::StatsD)
+                    .into_iter()
+                    .chain(otlp.map(push::Target::Otlp));
+                for target in targets {
+                    let pusher = push::Pusher::new(metrics.clone(), target, metrics_prefix.clone());
+                    pushers.push(tokio::spawn(pusher.run(stop_rx.clone())));
+                }
+                engine.metrics = Some(metrics);
+            }
+            let campaigns = campaigns
+                .into_iter()
+                .map(|v| {
+                    let campaign = config.campaigns.get(&v).unwrap().clone();
+                    (v, campaign)
+                })
+                .collect::<Vec<_>>();
+            let raid = match loot {
                 | Some(loot) => {
                     let (tx, rx) = flume::unbounded::<String>();
                     let recorder = spawn(move || {
@@ -64,12 +159,23 @@ async fn main() -> Result<()> {
                             file_handle.write_all("\n".as_bytes()).unwrap();
                         }
                     });
-                    engine.raid(config.campaigns.get(&campaign).unwrap(), Some(tx)).await?;
+                    let raid = engine.raid_all(campaigns, concurrent, Some(tx)).await?;
                     recorder.join().unwrap();
+                    raid
                 },
-                | None => {
-                    engine.raid(config.campaigns.get(&campaign).unwrap(), None).await?;
-                },
+                | None => engine.raid_all(campaigns, concurrent, None).await?,
+            };
+            drop(stop_tx);
+            for pusher in pushers {
+                if let Err(e) = pusher.await? {
+                    eprintln!("can not push metrics: {}", e);
+                }
+            }
+            if let Some(path) = results {
+                std::fs::write(path, serde_json::to_string_pretty(&raid)?)?;
+            }
+            if let Some(path) = html {
+                std::fs::write(path, report::html(&raid)?)?;
             }
             Ok(())
         },
@@ -0,0 +1,350 @@
+use {
+    anyhow::Result,
+    rand::Rng,
+    std::{
+        collections::BTreeMap,
+        fmt::Write,
+        net::SocketAddr,
+        sync::{
+            Arc,
+            Mutex,
+        },
+        time::Duration,
+    },
+    tokio::{
+        io::{
+            AsyncReadExt,
+            AsyncWriteExt,
+        },
+        net::TcpListener,
+    },
+};
+
+/// Upper bounds (in seconds) of the latency histogram buckets.
+pub const BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Most latencies kept for StatsD timers between two pushes. Beyond that they
+/// are sampled.
+pub const MAX_SAMPLES: usize = 10_000;
+
+/// Campaign name and phase index.
+pub type PhaseKey = (String, usize);
+
+/// Live metrics of a raid, fed by the status loops of all campaigns. They are
+/// rendered in the Prometheus text format or pushed (see `push`).
+#[derive(Debug, Default)]
+pub struct Metrics {
+    state: Mutex<State>,
+    /// Whether to keep every latency for pushing them as StatsD timers.
+    keep_samples: bool,
+    /// Latencies since they were last taken, if samples are kept.
+    samples: Mutex<Samples>,
+}
+
+/// A uniform sample of the latencies since the last push, at most
+/// `MAX_SAMPLES` of them.
+#[derive(Debug, Default)]
+pub struct Samples {
+    pub latencies: Vec<(PhaseKey, Duration)>,
+    /// Latencies offered, kept or not.
+    pub seen: usize,
+}
+
+impl Samples {
+    fn add(&mut self, key: &PhaseKey, latency: Duration) {
+        self.seen += 1;
+        if self.latencies.len() < MAX_SAMPLES {
+            self.latencies.push((key.clone(), latency));
+        } else {
+            let idx = rand::thread_rng().gen_range(0..self.seen);
+            if idx < MAX_SAMPLES {
+                self.latencies[idx] = (key.clone(), latency);
+            }
+        }
+    }
+
+    /// Share of the latencies that were kept, from 0 to 1.
+    pub fn rate(&self) -> f64 {
+        if self.seen == 0 {
+            return 1.0;
+        }
+        self.latencies.len() as f64 / self.seen as f64
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct State {
+    /// Requests by phase, status and outcome.
+    pub requests: BTreeMap<(PhaseKey, String, &'static str), u64>,
+    pub latency: BTreeMap<PhaseKey, Histogram>,
+    pub in_flight: BTreeMap<PhaseKey, usize>,
+    pub active_threads: BTreeMap<PhaseKey, usize>,
+    /// Tags (labels) of the pushed metrics of a phase.
+    pub tags: BTreeMap<PhaseKey, BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Histogram {
+    /// Counts per bucket, not cumulative. The last one is `+Inf`.
+    pub buckets: [u64; BUCKETS.len() + 1],
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl Metrics {
+    pub fn new(keep_samples: bool) -> Self {
+        Self {
+            state: Mutex::default(),
+            keep_samples,
+            samples: Mutex::default(),
+        }
+    }
+
+    /// Sets the tags of the pushed metrics of a phase.
+    pub fn tags(&self, campaign: &str, phase: usize, tags: BTreeMap<String, String>) {
+        self.state
+            .lock()
+            .unwrap()
+            .tags
+            .insert((campaign.to_owned(), phase), tags);
+    }
+
+    pub fn snapshot(&self) -> State {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Takes the latencies kept since the last call.
+    pub fn take_samples(&self) -> Samples {
+        std::mem::take(&mut self.samples.lock().unwrap())
+    }
+
+    /// Counts a finished request. `status` is empty where it makes no sense
+    /// as a label (errors, message based phases).
+    pub fn request(&self, campaign: &str, phase: usize, status: &str, outcome: &'static str, latency: Duration) {
+        self.count(campaign, phase, status, outcome);
+        let key = (campaign.to_owned(), phase);
+        let mut state = self.state.lock().unwrap();
+        if self.keep_samples {
+            self.samples.lock().unwrap().add(&key, latency);
+        }
+        let histogram = state.latency.entry(key).or_default();
+        let seconds = latency.as_secs_f64();
+        let bucket = BUCKETS.iter().position(|v| seconds <= *v).unwrap_or(BUCKETS.len());
+        histogram.buckets[bucket] += 1;
+        histogram.sum += seconds;
+        histogram.count += 1;
+    }
+
+    /// Counts a request without a latency, e.g. one that was never sent.
+    pub fn count(&self, campaign: &str, phase: usize, status: &str, outcome: &'static str) {
+        let key = (campaign.to_owned(), phase);
+        *self
+            .state
+            .lock()
+            .unwrap()
+            .requests
+            .entry((key, status.to_owned(), outcome))
+            .or_default() += 1;
+    }
+
+    /// Updates the gauges of a phase.
+    pub fn phase(&self, campaign: &str, phase: usize, active_threads: usize, in_flight: usize) {
+        let key = (campaign.to_owned(), phase);
+        let mut state = self.state.lock().unwrap();
+        state.active_threads.insert(key.clone(), active_threads);
+        state.in_flight.insert(key, in_flight);
+    }
+
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+        let labels = |key: &PhaseKey| format!("campaign=\"{}\",phase=\"{}\"", escape(&key.0), key.1);
+
+        out.push_str("# HELP viking_requests_total Finished requests.\n");
+        out.push_str("# TYPE viking_requests_total counter\n");
+        for ((key, status, outcome), v) in &state.requests {
+            let _ = writeln!(
+                out,
+                "viking_requests_total{{{},status=\"{}\",outcome=\"{}\"}} {}",
+                labels(key),
+                escape(status),
+                outcome,
+                v
+            );
+        }
+
+        out.push_str("# HELP viking_request_duration_seconds Latency of finished requests.\n");
+        out.push_str("# TYPE viking_request_duration_seconds histogram\n");
+        for (key, h) in &state.latency {
+            let mut cumulative = 0;
+            for (idx, count) in h.buckets.iter().enumerate() {
+                cumulative += count;
+                let le = BUCKETS.get(idx).map_or("+Inf".to_owned(), |v| v.to_string());
+                let _ = writeln!(
+                    out,
+                    "viking_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels(key),
+                    le,
+                    cumulative
+                );
+            }
+            let _ = writeln!(out, "viking_request_duration_seconds_sum{{{}}} {}", labels(key), h.sum);
+            let _ = writeln!(
+                out,
+                "viking_request_duration_seconds_count{{{}}} {}",
+                labels(key),
+                h.count
+            );
+        }
+
+        out.push_str("# HELP viking_in_flight_requests Requests picked up by worker threads and not finished yet.\n");
+        out.push_str("# TYPE viking_in_flight_requests gauge\n");
+        for (key, v) in &state.in_flight {
+            let _ = writeln!(out, "viking_in_flight_requests{{{}}} {}", labels(key), v);
+        }
+
+        out.push_str("# HELP viking_active_threads Worker threads that have not finished yet.\n");
+        out.push_str("# TYPE viking_active_threads gauge\n");
+        for (key, v) in &state.active_threads {
+            let _ = writeln!(out, "viking_active_threads{{{}}} {}", labels(key), v);
+        }
+        out
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Longest request head the metrics endpoint reads.
+const HEAD_LIMIT: usize = 8192;
+
+/// Binds `addr` and serves the metrics on `/metrics` in the background.
+/// Returns the bound address.
+pub async fn serve(metrics: Arc<Metrics>, addr: SocketAddr) -> Result<SocketAddr> {
+    let listener = TcpListener::bind(addr).await?;
+    let local = listener.local_addr()?;
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let metrics = metrics.clone();
+            tokio::spawn(async move {
+                // reads the head of the request, a body is ignored
+                let mut head = Vec::new();
+                let mut buf = [0_u8; 1024];
+                while !head.windows(4).any(|v| v == b"\r\n\r\n") && head.len() < HEAD_LIMIT {
+                    match stream.read(&mut buf).await {
+                        | Ok(0) | Err(_) => break,
+                        | Ok(n) => head.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let (status, body) = route(&metrics, &head);
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-type: text/plain; version=0.0.4\r\ncontent-length: {}\r\nconnection: \
+                     close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+    Ok(local)
+}
+
+/// Status and body of the response to a request with the given head.
+fn route(metrics: &Metrics, head: &[u8]) -> (&'static str, String) {
+    let Some(end) = head.windows(4).position(|v| v == b"\r\n\r\n") else {
+        return ("400 Bad Request", String::new());
+    };
+    let head = String::from_utf8_lossy(&head[..end]);
+    let line = head.lines().next().unwrap_or_default();
+    match line.split(' ').collect::<Vec<_>>()[..] {
+        | [method, target, version] if version.starts_with("HTTP/1.") => {
+            match (method, target.split('?').next().unwrap_or_default()) {
+                | ("GET", "/metrics") => ("200 OK", metrics.render()),
+                | (_, "/metrics") => ("405 Method Not Allowed", String::new()),
+                | _ => ("404 Not Found", String::new()),
+            }
+        },
+        | _ => ("400 Bad Request", String::new()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_render() {
+        let metrics = Metrics::default();
+        metrics.request("main", 0, "200", "success", Duration::from_millis(20));
+        metrics.request("main", 0, "200", "success", Duration::from_secs(20));
+        metrics.request("main", 0, "", "client_error", Duration::from_millis(2));
+        metrics.phase("main", 0, 4, 3);
+
+        let out = metrics.render();
+        assert!(
+            out.contains("viking_requests_total{campaign=\"main\",phase=\"0\",status=\"200\",outcome=\"success\"} 2")
+        );
+        assert!(out.contains("viking_request_duration_seconds_bucket{campaign=\"main\",phase=\"0\",le=\"0.005\"} 1"));
+        assert!(out.contains("viking_request_duration_seconds_bucket{campaign=\"main\",phase=\"0\",le=\"0.025\"} 2"));
+        assert!(out.contains("viking_request_duration_seconds_bucket{campaign=\"main\",phase=\"0\",le=\"+Inf\"} 3"));
+        assert!(out.contains("viking_request_duration_seconds_count{campaign=\"main\",phase=\"0\"} 3"));
+        assert!(out.contains("viking_in_flight_requests{campaign=\"main\",phase=\"0\"} 3"));
+        assert!(out.contains("viking_active_threads{campaign=\"main\",phase=\"0\"} 4"));
+    }
+
+    #[test]
+    fn test_samples() {
+        let metrics = Metrics::new(true);
+        for idx in 0..MAX_SAMPLES * 4 {
+            metrics.request("main", 0, "200", "success", Duration::from_millis(idx as u64));
+        }
+        let samples = metrics.take_samples();
+        assert_eq!((samples.latencies.len(), samples.seen), (MAX_SAMPLES, MAX_SAMPLES * 4));
+        assert_eq!(samples.rate(), 0.25);
+        // the sample spans all requests, not just the first ones
+        assert!(samples
+            .latencies
+            .iter()
+            .any(|v| v.1 >= Duration::from_millis(MAX_SAMPLES as u64)));
+
+        let samples = metrics.take_samples();
+        assert_eq!((samples.latencies.len(), samples.rate()), (0, 1.0));
+        assert_eq!(Metrics::new(false).take_samples().seen, 0);
+    }
+
+    #[test]
+    fn test_route() {
+        let metrics = Metrics::default();
+        metrics.phase("main", 0, 1, 1);
+        let status = |head: &str| route(&metrics, head.as_bytes()).0;
+        assert_eq!(status("GET /metrics?x=1 HTTP/1.1\r\nhost: localhost\r\n\r\n"), "200 OK");
+        assert_eq!(status("GET /other HTTP/1.1\r\n\r\n"), "404 Not Found");
+        assert_eq!(status("POST /metrics HTTP/1.1\r\n\r\n"), "405 Method Not Allowed");
+        assert_eq!(status("GET /metrics HTTP/1.1\r\nhost: local"), "400 Bad Request");
+        assert_eq!(status("GET /metrics\r\n\r\n"), "400 Bad Request");
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let metrics = Arc::new(Metrics::default());
+        metrics.phase("main", 0, 2, 1);
+        let addr = serve(metrics, "127.0.0.1:0".parse().unwrap()).await.unwrap();
+
+        // a head that arrives in pieces is read to its end
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /metr").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        stream
+            .write_all(b"ics HTTP/1.1\r\nhost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("viking_active_threads{campaign=\"main\",phase=\"0\"} 2"));
+    }
+}
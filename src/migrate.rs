@@ -0,0 +1,263 @@
+use {
+    crate::{
+        compose,
+        config::WithVersion,
+        error::Error,
+    },
+    anyhow::Result,
+    serde_yaml::Value,
+    std::str::FromStr,
+};
+
+/// How many minor versions older than the CLI a configuration can be and still
+/// be raided with. Those configurations are migrated in memory on load.
+pub const COMPATIBILITY_WINDOW: u64 = 2;
+
+/// A `major.minor` configuration version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Version {
+    pub major: u64,
+    pub minor: u64,
+}
+
+impl Version {
+    /// The configuration version of this CLI.
+    pub fn current() -> Self {
+        env!("CARGO_PKG_VERSION").parse().unwrap()
+    }
+
+    /// Whether a configuration of this version can be raided with a CLI of
+    /// version `cli` after an in-memory migration.
+    pub fn within_window(&self, cli: &Self) -> bool {
+        self.major == cli.major && self <= cli && cli.minor - self.minor <= COMPATIBILITY_WINDOW
+    }
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('.');
+        let mut next = || {
+            parts
+                .next()
+                .and_then(|v| v.parse::<u64>().ok())
+                .ok_or_else(|| Error::VersionCompatibility(format!("version: {} is not a valid version", s)))
+        };
+        Ok(Self {
+            major: next()?,
+            minor: next()?,
+        })
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// A single change of the configuration format, made between versions `from`
+/// and `to`. `apply` upgrades a document of version `from` in place and
+/// returns a note for every change it made.
+struct Migration {
+    from: Version,
+    to: Version,
+    description: &'static str,
+    apply: fn(&mut Value) -> Vec<String>,
+}
+
+/// All changes of the configuration format, oldest first. Versions between
+/// them kept the format of the version before.
+const MIGRATIONS: &[Migration] = &[];
+
+/// What a migration changed in a document.
+#[derive(Debug)]
+pub(crate) struct Changes {
+    pub from: Version,
+    pub to: Version,
+    pub changes: Vec<(&'static str, Vec<String>)>,
+}
+
+impl std::fmt::Display for Changes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "migrated version {} to {}", self.from, self.to)?;
+        if self.changes.is_empty() {
+            writeln!(f, "no changes were necessary")?;
+        }
+        for (description, notes) in &self.changes {
+            writeln!(f, "- {}", description)?;
+            for note in notes {
+                writeln!(f, "  - {}", note)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Upgrades a configuration document to the current version.
+pub fn migrate(doc: &mut Value) -> Result<Changes> {
+    migrate_with(doc, MIGRATIONS, Version::current())
+}
+
+/// Upgrades a configuration document to version `to` with the `migrations`
+/// between its version and `to`. Only documents of the same major version can
+/// be migrated.
+fn migrate_with(doc: &mut Value, migrations: &[Migration], to: Version) -> Result<Changes> {
+    let from = version_of(doc)?;
+    if from > to {
+        return Err(Error::VersionCompatibility(format!("version: {} is newer than CLI {}", from, to)).into());
+    }
+    if from.major != to.major {
+        return Err(Error::VersionCompatibility(format!(
+            "version: {} can not be migrated to {}, only versions {}.x can",
+            from, to, to.major
+        ))
+        .into());
+    }
+
+    let mut changes = Vec::<(&'static str, Vec<String>)>::new();
+    for migration in migrations.iter().filter(|v| from <= v.from && v.to <= to) {
+        let notes = (migration.apply)(doc);
+        if !notes.is_empty() {
+            changes.push((migration.description, notes));
+        }
+    }
+    doc.as_mapping_mut()
+        .unwrap()
+        .insert("version".into(), Value::String(to.to_string()));
+
+    Ok(Changes { from, to, changes })
+}
+
+/// Makes sure a configuration document can be raided with this CLI. Documents
+/// of the current version are left as they are, documents within the
+/// compatibility window are migrated.
+pub fn ensure_compatible(doc: &mut Value) -> Result<Option<Changes>> {
+    let version = version_of(doc)?;
+    let cli = Version::current();
+    if version == cli {
+        return Ok(None);
+    }
+    if !version.within_window(&cli) {
+        return Err(Error::VersionCompatibility(format!(
+            "version: {} is not supported by CLI {} (try viking migrate)",
+            version, cli
+        ))
+        .into());
+    }
+    Ok(Some(migrate(doc)?))
+}
+
+fn version_of(doc: &Value) -> Result<Version> {
+    Ok(compose::deserialize::<WithVersion>(doc)?.version.parse()?)
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::config::Config,
+    };
+
+    fn version(v: &str) -> Version {
+        v.parse().unwrap()
+    }
+
+    fn doc(version: &str) -> Value {
+        serde_yaml::from_str::<Value>(
+            &include_str!("../res/example.yaml").replace("\"0.0\"", &format!("{:?}", version)),
+        )
+        .unwrap()
+    }
+
+    fn rename_campaign(doc: &mut Value) -> Vec<String> {
+        let campaigns = doc.get_mut("campaigns").unwrap().as_mapping_mut().unwrap();
+        let main = campaigns.remove("main").unwrap();
+        campaigns.insert("primary".into(), main);
+        vec!["campaigns.main -> campaigns.primary".into()]
+    }
+
+    fn add_group(doc: &mut Value) -> Vec<String> {
+        let phase = &mut doc["campaigns"]["primary"]["phases"][0];
+        phase.as_mapping_mut().unwrap().insert("group".into(), "a".into());
+        vec!["campaigns.primary.phases[0].group: a".into()]
+    }
+
+    const TEST_MIGRATIONS: &[Migration] = &[
+        Migration {
+            from: Version { major: 1, minor: 0 },
+            to: Version { major: 1, minor: 1 },
+            description: "campaigns are renamed",
+            apply: rename_campaign,
+        },
+        Migration {
+            from: Version { major: 1, minor: 2 },
+            to: Version { major: 1, minor: 3 },
+            description: "phases have a group",
+            apply: add_group,
+        },
+    ];
+
+    #[tokio::test]
+    async fn test_migrate() {
+        let mut old = doc("1.0");
+        let changes = migrate_with(&mut old, TEST_MIGRATIONS, version("1.3")).unwrap();
+        assert_eq!((changes.from, changes.to), (version("1.0"), version("1.3")));
+        assert_eq!(changes.changes.len(), 2);
+        assert_eq!(changes.changes[1].1, vec!["campaigns.primary.phases[0].group: a"]);
+        let config = compose::deserialize::<Config>(&old).unwrap();
+        assert_eq!(config.campaigns["primary"].phases[0].group.as_deref(), Some("a"));
+        assert_eq!(old["version"], Value::String("1.3".into()));
+
+        // only the migrations after the version of the document apply
+        let mut doc_1_1 = doc("1.1");
+        rename_campaign(&mut doc_1_1);
+        let changes = migrate_with(&mut doc_1_1, TEST_MIGRATIONS, version("1.3")).unwrap();
+        assert_eq!(changes.changes.len(), 1);
+        assert_eq!(changes.changes[0].0, "phases have a group");
+
+        // migrating twice is a no-op
+        assert!(migrate_with(&mut old, TEST_MIGRATIONS, version("1.3"))
+            .unwrap()
+            .changes
+            .is_empty());
+        // and migrations up to an older version leave newer changes out
+        let mut doc_1_0 = doc("1.0");
+        assert_eq!(
+            migrate_with(&mut doc_1_0, TEST_MIGRATIONS, version("1.2"))
+                .unwrap()
+                .changes
+                .len(),
+            1
+        );
+
+        assert!(migrate_with(&mut doc("1.4"), TEST_MIGRATIONS, version("1.3")).is_err());
+        assert!(migrate_with(&mut doc("0.9"), TEST_MIGRATIONS, version("1.3")).is_err());
+        // documents of the current version are left as they are
+        let mut current =
+            serde_yaml::from_str::<Value>(&include_str!("../res/example.yaml").replace("#time: !s 60", "time: !s 60"))
+                .unwrap();
+        let current_tagged = serde_yaml::to_string(&current).unwrap();
+        assert!(migrate(&mut current).unwrap().changes.is_empty());
+        assert_eq!(serde_yaml::to_string(&current).unwrap(), current_tagged);
+    }
+
+    #[tokio::test]
+    async fn test_migrations() {
+        for (idx, migration) in MIGRATIONS.iter().enumerate() {
+            assert!(migration.from < migration.to);
+            assert!(idx == 0 || MIGRATIONS[idx - 1].to <= migration.from);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_window() {
+        let cli = "1.4".parse::<Version>().unwrap();
+        assert!("1.4".parse::<Version>().unwrap().within_window(&cli));
+        assert!("1.2".parse::<Version>().unwrap().within_window(&cli));
+        assert!(!"1.1".parse::<Version>().unwrap().within_window(&cli));
+        assert!(!"1.5".parse::<Version>().unwrap().within_window(&cli));
+        assert!(!"0.4".parse::<Version>().unwrap().within_window(&cli));
+    }
+}
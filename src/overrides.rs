@@ -0,0 +1,379 @@
+use {
+    crate::{
+        compose,
+        config::Config,
+        error::Error,
+    },
+    anyhow::Result,
+    serde_yaml::{
+        Mapping,
+        Value,
+    },
+    std::str::FromStr,
+};
+
+/// Prefix of environment variables that override configuration values.
+pub const ENV_PREFIX: &str = "VIKING_SET__";
+
+/// Paths of the enums with data in a phase, `*` standing for any key or
+/// index. Setting a variant of one of them replaces the variant in the
+/// document instead of being added next to it.
+const ENUMS: &[&str] = &[
+    "target",
+    "timeout",
+    "report.interval",
+    "ends.time",
+    "spec",
+    "spec.get.header.*.*",
+    "spec.get.query.*.*",
+    "spec.web_socket.header.*.*",
+    "spec.web_socket.messages.*",
+    "spec.web_socket.interval",
+    "spec.grpc.metadata.*.*",
+    "spec.stream.header.*.*",
+    "spec.stream.lifetime",
+    "spec.tcp.payloads.*",
+    "spec.tcp.read",
+    "spec.tcp.read.idle",
+    "spec.udp.payloads.*",
+    "spec.udp.read",
+    "spec.udp.read.idle",
+    "spec.graphql.header.*.*",
+    "behaviours.error.backoff",
+    "client.connect_timeout",
+    "client.pool.idle_timeout",
+    "client.proxy",
+    "client.tls.identity",
+    "client.tls.identity.pkcs12.password",
+    "auth",
+    "auth.basic.username",
+    "auth.basic.password",
+    "auth.bearer.token",
+    "auth.oauth2.token_url",
+    "auth.oauth2.client_id",
+    "auth.oauth2.client_secret",
+    "auth.oauth2.refresh_before",
+    "auth.hmac.key_id",
+    "auth.hmac.secret",
+    "session.login.target",
+    "session.login.header.*.*",
+    "session.login.form.*",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// A single `path=value` override of a configuration value.
+///
+/// Paths starting with `campaigns` are resolved from the root of the
+/// configuration, paths starting with `phases` from every campaign and all
+/// other paths from every phase of every campaign. The `version` can not be
+/// overridden, it decides how the file is migrated before overrides apply.
+#[derive(Debug, Clone)]
+pub(crate) struct Override {
+    path: Vec<Segment>,
+    value: Value,
+}
+
+impl FromStr for Override {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, value) = s
+            .split_once('=')
+            .ok_or_else(|| Error::Argument(format!("override {} is not of the form path=value", s)))?;
+
+        let mut segments = Vec::<Segment>::new();
+        for part in path.split('.') {
+            let (key, indices) = match part.find('[') {
+                | Some(v) => part.split_at(v),
+                | None => (part, ""),
+            };
+            if !key.is_empty() {
+                segments.push(Segment::Key(key.to_owned()));
+            }
+            for index in indices.split_terminator(']') {
+                let index = index
+                    .strip_prefix('[')
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .ok_or_else(|| Error::Argument(format!("invalid index in override path {}", path)))?;
+                segments.push(Segment::Index(index));
+            }
+        }
+        if segments.is_empty() || segments.iter().any(|v| matches!(v, Segment::Key(k) if k.is_empty())) {
+            return Err(Error::Argument(format!("invalid override path {}", path)));
+        }
+
+        Self::new(segments, value)
+    }
+}
+
+impl Override {
+    fn new(path: Vec<Segment>, value: &str) -> Result<Self, Error> {
+        if matches!(&path[0], Segment::Key(v) if v == "version") {
+            return Err(Error::Argument(
+                "the version can not be overridden (run viking migrate instead)".to_owned(),
+            ));
+        }
+        Ok(Self {
+            path,
+            value: Self::parse_value(value)?,
+        })
+    }
+
+    /// Reads an override from an environment variable like
+    /// `VIKING_SET__PHASES__0__THREADS=64`. Path segments are separated by
+    /// `__`, numeric segments are indices and keys are lowercased.
+    pub fn from_env_var(name: &str, value: &str) -> Result<Option<Self>, Error> {
+        let path = match name.strip_prefix(ENV_PREFIX) {
+            | Some(v) if !v.is_empty() => v,
+            | _ => return Ok(None),
+        };
+        let path = path
+            .split("__")
+            .map(|v| {
+                match v.parse::<usize>() {
+                    | Ok(v) => Segment::Index(v),
+                    | Err(_) => Segment::Key(v.to_lowercase()),
+                }
+            })
+            .collect::<Vec<_>>();
+        if path.iter().any(|v| matches!(v, Segment::Key(k) if k.is_empty())) {
+            return Err(Error::Argument(format!("invalid override variable {}", name)));
+        }
+
+        Ok(Some(Self::new(path, value)?))
+    }
+
+    /// Collects all overrides from the environment, in a stable order.
+    pub fn from_env() -> Result<Vec<Self>, Error> {
+        let mut vars = std::env::vars()
+            .filter(|v| v.0.starts_with(ENV_PREFIX))
+            .collect::<Vec<_>>();
+        vars.sort();
+        let mut overrides = Vec::<Self>::new();
+        for (name, value) in vars {
+            if let Some(v) = Self::from_env_var(&name, &value)? {
+                overrides.push(v);
+            }
+        }
+        Ok(overrides)
+    }
+
+    fn parse_value(value: &str) -> Result<Value, Error> {
+        serde_yaml::from_str::<Value>(value)
+            .map_err(|e| Error::Argument(format!("invalid override value {}: {}", value, e)))
+    }
+
+    /// Applies the override to a composed configuration document and returns
+    /// the paths it set.
+    fn apply(&self, doc: &mut Value) -> Result<Vec<Vec<Segment>>> {
+        let prefixes = match &self.path[0] {
+            | Segment::Key(v) if v == "campaigns" => vec![Vec::new()],
+            | _ => {
+                let campaigns = match doc.get("campaigns").and_then(|v| v.as_mapping()) {
+                    | Some(v) => v,
+                    | None => return Ok(Vec::new()),
+                };
+                let mut prefixes = Vec::<Vec<Segment>>::new();
+                for (name, value) in campaigns {
+                    let campaign = vec![
+                        Segment::Key("campaigns".into()),
+                        Segment::Key(name.as_str().unwrap_or_default().to_owned()),
+                    ];
+                    if matches!(&self.path[0], Segment::Key(v) if v == "phases") {
+                        prefixes.push(campaign);
+                        continue;
+                    }
+                    let phases = value.get("phases").and_then(|v| v.as_sequence()).map_or(0, |v| v.len());
+                    for idx in 0..phases {
+                        let mut phase = campaign.clone();
+                        phase.extend([Segment::Key("phases".into()), Segment::Index(idx)]);
+                        prefixes.push(phase);
+                    }
+                }
+                prefixes
+            },
+        };
+        let mut paths = Vec::with_capacity(prefixes.len());
+        for prefix in prefixes {
+            let path = prefix.into_iter().chain(self.path.iter().cloned()).collect::<Vec<_>>();
+            self.set(doc, &path)?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    /// Sets the value at `path`. Where the path passes through an enum (a
+    /// duration, a value parser, a spec...), the variant set by the override
+    /// replaces the one in the document instead of being added next to it.
+    fn set(&self, doc: &mut Value, path: &[Segment]) -> Result<()> {
+        let mut current = doc;
+        for (idx, segment) in path.iter().enumerate() {
+            let last = idx == path.len() - 1;
+            current = match segment {
+                | Segment::Key(key) => {
+                    if current.is_null() {
+                        *current = Value::Mapping(Mapping::new());
+                    }
+                    let mapping = current
+                        .as_mapping_mut()
+                        .ok_or_else(|| Error::Argument(format!("override {} does not point to a mapping", self)))?;
+                    if is_enum(&path[..idx]) {
+                        mapping.retain(|k, _| k.as_str() == Some(key));
+                    }
+                    if last {
+                        mapping.insert(key.as_str().into(), self.value.clone());
+                        return Ok(());
+                    }
+                    mapping.entry(key.as_str().into()).or_insert(Value::Null)
+                },
+                | Segment::Index(index) => {
+                    let item = current
+                        .as_sequence_mut()
+                        .and_then(|v| v.get_mut(*index))
+                        .ok_or_else(|| Error::Argument(format!("override {} points to a missing item", self)))?;
+                    if last {
+                        *item = self.value.clone();
+                        return Ok(());
+                    }
+                    item
+                },
+            };
+        }
+        Ok(())
+    }
+}
+
+/// Applies `overrides` to a composed configuration document and reads the
+/// configuration from it. Fails for overrides of keys that the configuration
+/// does not know, which reading it would drop silently.
+pub(crate) fn apply(overrides: &[Override], doc: &mut Value) -> Result<Config> {
+    let mut paths = Vec::new();
+    for v in overrides {
+        paths.extend(v.apply(doc)?.into_iter().map(|path| (v, path)));
+    }
+    let config = compose::deserialize::<Config>(doc)?;
+    // YAML would tag enums, JSON keeps their variants as keys like the document
+    let typed = serde_json::to_value(&config)?;
+    for (v, path) in paths {
+        if get(&typed, &path).is_none() {
+            return Err(Error::Argument(format!("override {} does not match a configuration key", v)).into());
+        }
+    }
+    Ok(config)
+}
+
+/// Whether the mapping at `path` (from the root) is an enum, see `ENUMS`.
+fn is_enum(path: &[Segment]) -> bool {
+    let phase = match path {
+        | [Segment::Key(campaigns), Segment::Key(_), Segment::Key(phases), Segment::Index(_), phase @ ..]
+            if campaigns == "campaigns" && phases == "phases" =>
+        {
+            phase
+        },
+        | _ => return false,
+    };
+    ENUMS.iter().any(|pattern| {
+        let pattern = pattern.split('.').collect::<Vec<_>>();
+        pattern.len() == phase.len()
+            && pattern
+                .iter()
+                .zip(phase)
+                .all(|(p, s)| *p == "*" || matches!(s, Segment::Key(k) if k == p))
+    })
+}
+
+/// The value at `path`, if there is one.
+fn get<'a>(doc: &'a serde_json::Value, path: &[Segment]) -> Option<&'a serde_json::Value> {
+    path.iter().try_fold(doc, |current, segment| {
+        match segment {
+            | Segment::Key(v) => current.get(v.as_str()),
+            | Segment::Index(v) => current.get(*v),
+        }
+    })
+}
+
+impl std::fmt::Display for Override {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, segment) in self.path.iter().enumerate() {
+            match segment {
+                | Segment::Key(v) if idx == 0 => write!(f, "{}", v)?,
+                | Segment::Key(v) => write!(f, ".{}", v)?,
+                | Segment::Index(v) => write!(f, "[{}]", v)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::config::{
+            Spec,
+            ValueParser,
+        },
+    };
+
+    fn example() -> Value {
+        serde_yaml::from_str::<Value>(include_str!("../res/example.yaml")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_override() {
+        let mut doc = example();
+        let overrides = [
+            "phases[0].threads=64".parse::<Override>().unwrap(),
+            "target.static=https://staging.example.com".parse().unwrap(),
+            Override::from_env_var("VIKING_SET__ENDS__TIME", "{s: 60}")
+                .unwrap()
+                .unwrap(),
+            // header names that are variant names elsewhere are not stripped
+            "spec.get.header.static=[{static: a}]".parse().unwrap(),
+            "timeout.ms=500".parse().unwrap(),
+        ];
+        let config = apply(&overrides, &mut doc).unwrap();
+        assert!("phases[x].threads=1".parse::<Override>().is_err());
+
+        let phase = &config.campaigns.get("main").unwrap().phases[0];
+        assert_eq!(phase.threads, 64);
+        assert!(matches!(&phase.target, ValueParser::Static(v) if v == "https://staging.example.com"));
+        assert_eq!(phase.ends.requests, Some(500));
+        assert_eq!(phase.ends.time.as_ref().unwrap().to_ms(), 60000);
+        assert_eq!(phase.timeout.to_ms(), 500);
+        match &phase.spec {
+            | Spec::Get { header, .. } => {
+                assert!(header.contains_key("x-api-key"));
+                assert!(header.contains_key("static"));
+            },
+            | _ => panic!("expected a get spec"),
+        }
+
+        // another variant replaces the one in the document
+        let config = apply(&["timeout.s=2".parse().unwrap()], &mut doc).unwrap();
+        assert_eq!(config.campaigns.get("main").unwrap().phases[0].timeout.to_ms(), 2000);
+    }
+
+    #[test]
+    fn test_invalid_override() {
+        let error = |v: &str| apply(&[v.parse().unwrap()], &mut example()).err().unwrap().to_string();
+        assert!(error("phases[3].threads=1").contains("missing item"));
+        // keys the configuration does not know would be dropped
+        assert!(error("threds=1").contains("does not match a configuration key"));
+        assert!(error("client.keep_alives=false").contains("does not match a configuration key"));
+        assert!(error("campaigns.main.phases[0].spec.get.headers={}").contains("does not match"));
+
+        // the version decides the migration, which runs before the overrides
+        assert!("version=0.1.0".parse::<Override>().is_err());
+        assert!(Override::from_env_var("VIKING_SET__VERSION", "0.1.0").is_err());
+        // only variables with the dedicated prefix are overrides
+        assert!(Override::from_env_var("VIKING_PHASES__0__THREADS", "1")
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,409 @@
+use {
+    crate::{
+        error::Error,
+        metrics::{
+            Metrics,
+            PhaseKey,
+            Samples,
+            State,
+            BUCKETS,
+        },
+    },
+    anyhow::Result,
+    std::{
+        collections::BTreeMap,
+        net::SocketAddr,
+        sync::Arc,
+        time::{
+            Duration,
+            SystemTime,
+            UNIX_EPOCH,
+        },
+    },
+    tokio::net::UdpSocket,
+};
+
+/// How often metrics are pushed while raiding. They are pushed a last time
+/// when the raid ends.
+pub const INTERVAL: Duration = Duration::from_secs(10);
+
+/// Maximum size of a StatsD datagram, small enough to not be fragmented.
+const DATAGRAM_SIZE: usize = 1400;
+
+pub enum Target {
+    /// StatsD server with DogStatsD style tags.
+    StatsD(SocketAddr),
+    /// OTLP/HTTP collector, e.g. `http://localhost:4318`. Metrics are posted as
+    /// JSON to `/v1/metrics`.
+    Otlp(String),
+}
+
+/// Pushes the metrics of a raid to a StatsD server or an OTLP collector.
+pub struct Pusher {
+    metrics: Arc<Metrics>,
+    target: Target,
+    prefix: String,
+    start: SystemTime,
+    /// Request counters as of the last push. StatsD counters are deltas.
+    pushed: BTreeMap<(PhaseKey, String, &'static str), u64>,
+}
+
+impl Pusher {
+    pub fn new(metrics: Arc<Metrics>, target: Target, prefix: String) -> Self {
+        Self {
+            metrics,
+            target,
+            prefix,
+            start: SystemTime::now(),
+            pushed: BTreeMap::new(),
+        }
+    }
+
+    /// Pushes every `INTERVAL` until `stop` is dropped, then a last time.
+    pub async fn run(mut self, stop: flume::Receiver<()>) -> Result<()> {
+        let mut interval = tokio::time::interval(INTERVAL);
+        interval.tick().await;
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    // a collector that is down must not end the raid, the next push catches up
+                    let _ = self.push().await;
+                },
+                _ = stop.recv_async() => return self.push().await,
+            }
+        }
+    }
+
+    pub async fn push(&mut self) -> Result<()> {
+        let state = self.metrics.snapshot();
+        match &self.target {
+            | Target::StatsD(addr) => {
+                let datagrams = self.statsd(&state, &self.metrics.take_samples());
+                let local = match addr {
+                    | SocketAddr::V4(_) => "0.0.0.0:0",
+                    | SocketAddr::V6(_) => "[::]:0",
+                };
+                let socket = UdpSocket::bind(local).await?;
+                for v in datagrams {
+                    socket.send_to(v.as_bytes(), addr).await?;
+                }
+                self.pushed = state.requests;
+            },
+            | Target::Otlp(endpoint) => {
+                let url = match endpoint.trim_end_matches('/') {
+                    | v if v.ends_with("/v1/metrics") => v.to_owned(),
+                    | v => format!("{}/v1/metrics", v),
+                };
+                let response = reqwest::Client::new()
+                    .post(&url)
+                    .json(&self.otlp(&state))
+                    .send()
+                    .await?;
+                if !response.status().is_success() {
+                    return Err(Error::Config(format!("otlp collector responded with {}", response.status())).into());
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// StatsD lines, packed into datagrams.
+    fn statsd(&self, state: &State, samples: &Samples) -> Vec<String> {
+        let tags = |key: &PhaseKey, extra: &[(&str, &str)]| {
+            let phase = key.1.to_string();
+            let mut tags = vec![("campaign", key.0.as_str()), ("phase", phase.as_str())];
+            tags.extend(extra.iter().filter(|v| !v.1.is_empty()));
+            let custom = state.tags.get(key);
+            tags.extend(
+                custom
+                    .iter()
+                    .flat_map(|v| v.iter().map(|v| (v.0.as_str(), v.1.as_str()))),
+            );
+            tags.iter()
+                .map(|(k, v)| format!("{}:{}", statsd_tag(k), statsd_tag(v)))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+
+        let mut lines = Vec::<String>::new();
+        for (key, count) in &state.requests {
+            let delta = count - self.pushed.get(key).copied().unwrap_or_default();
+            if delta > 0 {
+                let (phase, status, outcome) = key;
+                let tags = tags(phase, &[("status", status), ("outcome", outcome)]);
+                lines.push(format!("{}.requests:{}|c|#{}", self.prefix, delta, tags));
+            }
+        }
+        // sampled timers tell StatsD how many requests they stand for
+        let rate = match samples.rate() {
+            | v if v < 1.0 => format!("|@{:.6}", v),
+            | _ => "".to_owned(),
+        };
+        for (key, latency) in &samples.latencies {
+            let ms = latency.as_secs_f64() * 1000.0;
+            lines.push(format!(
+                "{}.request_duration:{:.3}|ms{}|#{}",
+                self.prefix,
+                ms,
+                rate,
+                tags(key, &[])
+            ));
+        }
+        for (key, v) in &state.in_flight {
+            lines.push(format!(
+                "{}.in_flight_requests:{}|g|#{}",
+                self.prefix,
+                v,
+                tags(key, &[])
+            ));
+        }
+        for (key, v) in &state.active_threads {
+            lines.push(format!("{}.active_threads:{}|g|#{}", self.prefix, v, tags(key, &[])));
+        }
+
+        let mut datagrams = Vec::<String>::new();
+        for line in lines {
+            match datagrams.last_mut() {
+                | Some(v) if v.len() + 1 + line.len() <= DATAGRAM_SIZE => {
+                    v.push('\n');
+                    v.push_str(&line);
+                },
+                | _ => datagrams.push(line),
+            }
+        }
+        datagrams
+    }
+
+    /// An OTLP `ExportMetricsServiceRequest` in its JSON form. Sums and
+    /// histograms are cumulative since the start of the raid.
+    fn otlp(&self, state: &State) -> serde_json::Value {
+        let start = nanos(self.start);
+        let now = nanos(SystemTime::now());
+        let attributes = |key: &PhaseKey, extra: &[(&str, &str)]| {
+            let phase = key.1.to_string();
+            let mut attributes = vec![("campaign", key.0.as_str()), ("phase", phase.as_str())];
+            attributes.extend(extra.iter().filter(|v| !v.1.is_empty()));
+            let custom = state.tags.get(key);
+            attributes.extend(
+                custom
+                    .iter()
+                    .flat_map(|v| v.iter().map(|v| (v.0.as_str(), v.1.as_str()))),
+            );
+            attributes
+                .iter()
+                .map(|(k, v)| serde_json::json!({ "key": k, "value": { "stringValue": v } }))
+                .collect::<Vec<_>>()
+        };
+        let gauge = |name: &str, values: &BTreeMap<PhaseKey, usize>| {
+            serde_json::json!({
+                "name": format!("{}.{}", self.prefix, name),
+                "unit": "1",
+                "gauge": {
+                    "dataPoints": values.iter().map(|(key, v)| serde_json::json!({
+                        "attributes": attributes(key, &[]),
+                        "timeUnixNano": now,
+                        "asInt": v.to_string(),
+                    })).collect::<Vec<_>>(),
+                },
+            })
+        };
+
+        let requests = state
+            .requests
+            .iter()
+            .map(|((key, status, outcome), v)| {
+                serde_json::json!({
+                    "attributes": attributes(key, &[("status", status), ("outcome", outcome)]),
+                    "startTimeUnixNano": start,
+                    "timeUnixNano": now,
+                    "asInt": v.to_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let latency = state
+            .latency
+            .iter()
+            .map(|(key, h)| {
+                serde_json::json!({
+                    "attributes": attributes(key, &[]),
+                    "startTimeUnixNano": start,
+                    "timeUnixNano": now,
+                    "count": h.count.to_string(),
+                    "sum": h.sum,
+                    "bucketCounts": h.buckets.iter().map(|v| v.to_string()).collect::<Vec<_>>(),
+                    "explicitBounds": BUCKETS,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        serde_json::json!({
+            "resourceMetrics": [{
+                "resource": {
+                    "attributes": [{ "key": "service.name", "value": { "stringValue": "viking" } }],
+                },
+                "scopeMetrics": [{
+                    "scope": { "name": "viking", "version": env!("CARGO_PKG_VERSION") },
+                    "metrics": [
+                        {
+                            "name": format!("{}.requests", self.prefix),
+                            "unit": "1",
+                            "sum": {
+                                "aggregationTemporality": 2,
+                                "isMonotonic": true,
+                                "dataPoints": requests,
+                            },
+                        },
+                        {
+                            "name": format!("{}.request.duration", self.prefix),
+                            "unit": "s",
+                            "histogram": {
+                                "aggregationTemporality": 2,
+                                "dataPoints": latency,
+                            },
+                        },
+                        gauge("in_flight_requests", &state.in_flight),
+                        gauge("active_threads", &state.active_threads),
+                    ],
+                }],
+            }],
+        })
+    }
+}
+
+/// Replaces the characters that separate StatsD tags and fields.
+fn statsd_tag(value: &str) -> String {
+    value.replace([',', '|', '#', ':', '\n'], "_")
+}
+
+fn nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        tokio::{
+            io::{
+                AsyncReadExt,
+                AsyncWriteExt,
+            },
+            net::TcpListener,
+        },
+    };
+
+    fn metrics() -> Arc<Metrics> {
+        let metrics = Arc::new(Metrics::new(true));
+        metrics.tags("main", 0, BTreeMap::from([("env".to_owned(), "staging".to_owned())]));
+        metrics.request("main", 0, "200", "success", Duration::from_millis(20));
+        metrics.request("main", 0, "200", "success", Duration::from_millis(30));
+        metrics.phase("main", 0, 2, 1);
+        metrics
+    }
+
+    #[tokio::test]
+    async fn test_statsd() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let metrics = metrics();
+        let mut pusher = Pusher::new(
+            metrics.clone(),
+            Target::StatsD(server.local_addr().unwrap()),
+            "load".into(),
+        );
+        pusher.push().await.unwrap();
+        let mut buf = [0_u8; DATAGRAM_SIZE];
+        let n = server.recv(&mut buf).await.unwrap();
+        let datagram = String::from_utf8_lossy(&buf[..n]).into_owned();
+        let lines = datagram.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[0],
+            "load.requests:2|c|#campaign:main,phase:0,status:200,outcome:success,env:staging"
+        );
+        assert_eq!(
+            lines[1],
+            "load.request_duration:20.000|ms|#campaign:main,phase:0,env:staging"
+        );
+        assert_eq!(
+            lines[3],
+            "load.in_flight_requests:1|g|#campaign:main,phase:0,env:staging"
+        );
+
+        // counters are deltas, samples are sent once
+        metrics.request("main", 0, "200", "success", Duration::from_millis(10));
+        pusher.push().await.unwrap();
+        let n = server.recv(&mut buf).await.unwrap();
+        let datagram = String::from_utf8_lossy(&buf[..n]).into_owned();
+        assert!(datagram.starts_with("load.requests:1|c|"));
+        assert_eq!(datagram.lines().count(), 4);
+    }
+
+    #[test]
+    fn test_statsd_sample_rate() {
+        let metrics = Arc::new(Metrics::new(true));
+        for _ in 0..crate::metrics::MAX_SAMPLES * 2 {
+            metrics.request("main", 0, "200", "success", Duration::from_millis(20));
+        }
+        let pusher = Pusher::new(
+            metrics.clone(),
+            Target::StatsD("127.0.0.1:1".parse().unwrap()),
+            "load".into(),
+        );
+        let datagrams = pusher.statsd(&metrics.snapshot(), &metrics.take_samples());
+        let timers = datagrams
+            .iter()
+            .flat_map(|v| v.lines())
+            .filter(|v| v.starts_with("load.request_duration:"))
+            .collect::<Vec<_>>();
+        assert_eq!(timers.len(), crate::metrics::MAX_SAMPLES);
+        assert_eq!(
+            timers[0],
+            "load.request_duration:20.000|ms|@0.500000|#campaign:main,phase:0"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_otlp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0_u8; 4096];
+            // read until the whole body (by content length) arrived
+            loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).into_owned();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|v| v.to_lowercase().strip_prefix("content-length: ").map(|v| v.to_owned()))
+                        .unwrap();
+                    if body.len() >= length.parse::<usize>().unwrap() {
+                        stream
+                            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                            .await
+                            .unwrap();
+                        return (head.to_owned(), body.to_owned());
+                    }
+                }
+            }
+        });
+
+        let mut pusher = Pusher::new(metrics(), Target::Otlp(endpoint), "viking".into());
+        pusher.push().await.unwrap();
+        let (head, body) = server.await.unwrap();
+        assert!(head.starts_with("POST /v1/metrics "));
+        let body = serde_json::from_str::<serde_json::Value>(&body).unwrap();
+        let metrics = &body["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(metrics[0]["name"], "viking.requests");
+        assert_eq!(metrics[0]["sum"]["dataPoints"][0]["asInt"], "2");
+        assert_eq!(metrics[1]["histogram"]["dataPoints"][0]["count"], "2");
+        let attributes = metrics[0]["sum"]["dataPoints"][0]["attributes"].as_array().unwrap();
+        assert!(attributes.contains(&serde_json::json!({ "key": "env", "value": { "stringValue": "staging" } })));
+    }
+}
@@ -0,0 +1,455 @@
+use {
+    crate::{
+        redact,
+        results::{
+            PhaseResults,
+            RaidResults,
+        },
+    },
+    anyhow::Result,
+    std::{
+        collections::BTreeMap,
+        fmt::Write,
+    },
+};
+
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 220.0;
+/// Space for the axis labels.
+const MARGIN: f64 = 48.0;
+const COLORS: &[&str] = &[
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7", "#9c755f", "#bab0ac",
+];
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em \
+                     auto;max-width:1000px;color:#222}table{border-collapse:collapse;margin:1em 0}th,td{border:1px \
+                     solid #ccc;padding:4px \
+                     8px;text-align:right}th:first-child,td:first-child{text-align:left}pre{background:#f5f5f5;\
+                     padding:1em;overflow:auto}.charts{display:flex;flex-wrap:wrap;gap:1em}svg \
+                     text{font-size:11px;fill:#555}";
+
+/// Renders the results of a raid as a single HTML page without external
+/// resources.
+pub fn html(results: &RaidResults) -> Result<String> {
+    let mut out = String::new();
+    write!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>viking raid \
+         report</title><style>{}</style></head><body>",
+        STYLE
+    )?;
+    write!(
+        out,
+        "<h1>Raid report</h1><p>{} campaign(s), took {:.2} seconds.</p>",
+        results.campaigns.len(),
+        results.elapsed_ms as f64 / 1000.0
+    )?;
+
+    for (name, campaign) in &results.campaigns {
+        write!(
+            out,
+            "<h2>Campaign \"{}\"</h2><p>Took {:.2} seconds.</p>",
+            escape(name),
+            campaign.elapsed_ms as f64 / 1000.0
+        )?;
+        out.push_str(
+            "<table><tr><th>Phase</th><th>Requests</th><th>OK</th><th>Error</th><th>Request error</th><th>Requests / \
+             s</th><th>p50 (ms)</th><th>p90 (ms)</th><th>p95 (ms)</th><th>p99 (ms)</th><th>Max (ms)</th></tr>",
+        );
+        for (p_idx, phase) in campaign.phases.iter().enumerate() {
+            summary_row(&mut out, &phase_label(p_idx, phase), phase)?;
+        }
+        summary_row(&mut out, "Combined", &campaign.combined())?;
+        out.push_str("</table>");
+
+        for (p_idx, phase) in campaign.phases.iter().enumerate() {
+            write!(
+                out,
+                "<h3>{}</h3><div class=\"charts\">",
+                escape(&phase_label(p_idx, phase))
+            )?;
+            let point = |f: fn(&crate::timeseries::Row) -> Option<f64>| {
+                phase
+                    .series
+                    .iter()
+                    .filter_map(|v| f(v).map(|y| (v.elapsed_ms as f64 / 1000.0, y)))
+                    .collect::<Vec<_>>()
+            };
+            if !phase.series.is_empty() {
+                out.push_str(&line_chart("Throughput (requests / s)", &[(
+                    "requests / s",
+                    point(|v| Some(v.rps)),
+                )]));
+                out.push_str(&line_chart("Latency (ms)", &[
+                    ("p50", point(|v| v.latency_p50_ms)),
+                    ("p90", point(|v| v.latency_p90_ms)),
+                    ("p99", point(|v| v.latency_p99_ms)),
+                ]));
+            }
+            let mut slices = phase.statuses.iter().map(|(k, v)| (k.clone(), *v)).collect::<Vec<_>>();
+            if !slices.is_empty() && phase.client_error > 0 {
+                slices.push(("request error".to_owned(), phase.client_error));
+            }
+            if !slices.is_empty() {
+                out.push_str(&pie("Status codes", &slices));
+            }
+            out.push_str("</div>");
+            errors(&mut out, phase)?;
+            threads(&mut out, phase)?;
+        }
+    }
+
+    if !results.config.is_empty() {
+        // results of older versions may still carry secrets
+        let config = results
+            .config
+            .iter()
+            .map(|(k, v)| (k, redact::campaign(v)))
+            .collect::<BTreeMap<_, _>>();
+        write!(
+            out,
+            "<h2>Configuration</h2><pre>{}</pre>",
+            escape(&serde_yaml::to_string(&without_nulls(serde_yaml::to_value(&config)?))?)
+        )?;
+    }
+    out.push_str("</body></html>\n");
+    Ok(out)
+}
+
+/// Drops unset optional settings, which serialize as `null`.
+fn without_nulls(value: serde_yaml::Value) -> serde_yaml::Value {
+    match value {
+        | serde_yaml::Value::Mapping(v) => {
+            v.into_iter()
+                .filter(|v| !v.1.is_null())
+                .map(|(k, v)| (k, without_nulls(v)))
+                .collect::<serde_yaml::Mapping>()
+                .into()
+        },
+        | serde_yaml::Value::Sequence(v) => v.into_iter().map(without_nulls).collect(),
+        | serde_yaml::Value::Tagged(mut v) => {
+            v.value = without_nulls(v.value);
+            serde_yaml::Value::Tagged(v)
+        },
+        | v => v,
+    }
+}
+
+fn phase_label(p_idx: usize, phase: &PhaseResults) -> String {
+    match &phase.group {
+        | Some(v) => format!("Phase #{} ({})", p_idx, v),
+        | None => format!("Phase #{}", p_idx),
+    }
+}
+
+fn summary_row(out: &mut String, label: &str, phase: &PhaseResults) -> Result<()> {
+    let ms = |f: fn(&crate::results::LatencyResults) -> f64| {
+        phase
+            .latency
+            .as_ref()
+            .map_or("-".to_owned(), |v| format!("{:.2}", f(v)))
+    };
+    write!(
+        out,
+        "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.2}</td><td>{}</td><td>{}</td><td>{}</\
+         td><td>{}</td><td>{}</td></tr>",
+        escape(label),
+        phase.count,
+        phase.success,
+        phase.error,
+        phase.client_error,
+        phase.requests_per_second(),
+        ms(|v| v.p50_ms),
+        ms(|v| v.p90_ms),
+        ms(|v| v.p95_ms),
+        ms(|v| v.p99_ms),
+        ms(|v| v.max_ms)
+    )?;
+    Ok(())
+}
+
+fn errors(out: &mut String, phase: &PhaseResults) -> Result<()> {
+    if phase.error == 0 && phase.client_error == 0 {
+        return Ok(());
+    }
+    write!(
+        out,
+        "<p>{} request(s) marked as errors, {} request error(s).</p>",
+        phase.error, phase.client_error
+    )?;
+    for (class, v) in &phase.failures {
+        write!(
+            out,
+            "<details><summary>{} ({}, {} sample(s))</summary>",
+            escape(class),
+            v.count,
+            v.samples.len()
+        )?;
+        for sample in &v.samples {
+            write!(out, "<p><code>{}</code>", escape(&sample.request))?;
+            if let Some(v) = &sample.status {
+                write!(out, " responded with {}", escape(v))?;
+            }
+            out.push_str("</p>");
+            if !sample.headers.is_empty() {
+                out.push_str("<pre>");
+                for (k, v) in &sample.headers {
+                    writeln!(out, "{}: {}", escape(k), escape(v))?;
+                }
+                out.push_str("</pre>");
+            }
+            write!(out, "<pre>{}</pre>", escape(&sample.message))?;
+        }
+        out.push_str("</details>");
+    }
+    Ok(())
+}
+
+/// The stats of every thread, folded since phases may run many of them.
+fn threads(out: &mut String, phase: &PhaseResults) -> Result<()> {
+    if phase.threads.is_empty() {
+        return Ok(());
+    }
+    write!(
+        out,
+        "<details><summary>Threads \
+         ({})</summary><table><tr><th>Thread</th><th>Requests</th><th>OK</th><th>Error</th><th>Request \
+         error</th><th>Mean (ms)</th><th>p99 (ms)</th><th>Busy (%)</th></tr>",
+        phase.threads.len()
+    )?;
+    for t in &phase.threads {
+        let ms = |f: fn(&crate::results::LatencyResults) -> f64| {
+            t.latency.as_ref().map_or("-".to_owned(), |v| format!("{:.2}", f(v)))
+        };
+        write!(
+            out,
+            "<tr><td>#{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}</td></tr>",
+            t.thread,
+            t.count,
+            t.success,
+            t.error,
+            t.client_error,
+            ms(|v| v.mean_ms),
+            ms(|v| v.p99_ms),
+            t.busy_ratio * 100.0
+        )?;
+    }
+    out.push_str("</table></details>");
+    Ok(())
+}
+
+/// An SVG line chart of one or more series of (x, y) points. The x axis is
+/// the time since the phase started, in seconds.
+fn line_chart(title: &str, series: &[(&str, Vec<(f64, f64)>)]) -> String {
+    let points = series.iter().flat_map(|v| v.1.iter());
+    let max_x = points.clone().map(|v| v.0).fold(0.0, f64::max).max(1.0);
+    let max_y = points.map(|v| v.1).fold(0.0, f64::max).max(1.0);
+    let x = |v: f64| MARGIN + v / max_x * (WIDTH - 2.0 * MARGIN);
+    let y = |v: f64| HEIGHT - MARGIN / 2.0 - v / max_y * (HEIGHT - MARGIN);
+
+    let mut svg = format!(
+        "<svg width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\"><text x=\"{m}\" y=\"14\" \
+         style=\"font-weight:bold\">{t}</text>",
+        w = WIDTH,
+        h = HEIGHT,
+        m = MARGIN,
+        t = escape(title)
+    );
+    // axes with labels at zero, half and the maximum
+    let _ = write!(
+        svg,
+        "<polyline points=\"{},{} {},{} {},{}\" fill=\"none\" stroke=\"#999\"/>",
+        x(0.0),
+        y(max_y),
+        x(0.0),
+        y(0.0),
+        x(max_x),
+        y(0.0)
+    );
+    for v in [0.0, max_y / 2.0, max_y] {
+        let _ = write!(
+            svg,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>",
+            MARGIN - 4.0,
+            y(v) + 4.0,
+            number(v)
+        );
+    }
+    for v in [0.0, max_x / 2.0, max_x] {
+        let _ = write!(
+            svg,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}s</text>",
+            x(v),
+            HEIGHT - 4.0,
+            number(v)
+        );
+    }
+    for (idx, (name, points)) in series.iter().enumerate() {
+        let color = COLORS[idx % COLORS.len()];
+        let points = points
+            .iter()
+            .map(|v| format!("{:.1},{:.1}", x(v.0), y(v.1)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = write!(
+            svg,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\"/><text x=\"{}\" y=\"14\" \
+             style=\"fill:{}\">{}</text>",
+            points,
+            color,
+            WIDTH - MARGIN - 60.0 * (series.len() - idx - 1) as f64,
+            color,
+            escape(name)
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// An SVG pie chart with a legend of counts and shares.
+fn pie(title: &str, slices: &[(String, usize)]) -> String {
+    let total = slices.iter().map(|v| v.1).sum::<usize>().max(1) as f64;
+    let (cx, cy, r) = (90.0, 120.0, 80.0);
+    let mut svg = format!(
+        "<svg width=\"320\" height=\"{h}\" viewBox=\"0 0 320 {h}\"><text x=\"10\" y=\"14\" \
+         style=\"font-weight:bold\">{t}</text>",
+        h = HEIGHT,
+        t = escape(title)
+    );
+    let mut angle = 0.0_f64;
+    for (idx, (label, count)) in slices.iter().enumerate() {
+        let color = COLORS[idx % COLORS.len()];
+        let share = *count as f64 / total;
+        if share >= 1.0 {
+            let _ = write!(
+                svg,
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>",
+                cx, cy, r, color
+            );
+        } else if share > 0.0 {
+            let end = angle + share * std::f64::consts::TAU;
+            let point = |a: f64| (cx + r * a.sin(), cy - r * a.cos());
+            let (x1, y1) = point(angle);
+            let (x2, y2) = point(end);
+            let _ = write!(
+                svg,
+                "<path d=\"M{cx},{cy} L{x1:.2},{y1:.2} A{r},{r} 0 {large},1 {x2:.2},{y2:.2} Z\" fill=\"{color}\"/>",
+                large = u8::from(share > 0.5),
+            );
+            angle = end;
+        }
+        let _ = write!(
+            svg,
+            "<rect x=\"190\" y=\"{y}\" width=\"10\" height=\"10\" fill=\"{color}\"/><text x=\"205\" \
+             y=\"{ty}\">{label} ({count}, {share:.1}%)</text>",
+            y = 40 + idx * 16,
+            ty = 49 + idx * 16,
+            label = escape(label),
+            share = share * 100.0
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn number(v: f64) -> String {
+    if v >= 100.0 || v.fract() == 0.0 {
+        format!("{:.0}", v)
+    } else {
+        format!("{:.1}", v)
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::results::CampaignResults,
+        std::collections::BTreeMap,
+    };
+
+    #[test]
+    fn test_html() {
+        let results = serde_json::from_value::<RaidResults>(serde_json::json!({
+            "elapsed_ms": 2000,
+            "campaigns": {
+                "<main>": {
+                    "elapsed_ms": 2000,
+                    "phases": [{
+                        "elapsed_ms": 2000, "count": 10, "success": 8, "error": 1, "client_error": 1,
+                        "latency": {
+                            "count": 10, "min_ms": 1.0, "mean_ms": 5.0, "p50_ms": 4.0, "p90_ms": 9.0,
+                            "p95_ms": 9.5, "p99_ms": 9.9, "max_ms": 10.0,
+                        },
+                        "statuses": { "200": 8, "500": 1 },
+                        "series": [{
+                            "timestamp_ms": 0, "campaign": "<main>", "phase": 0, "elapsed_ms": 1000,
+                            "requests": 5, "rps": 5.0, "errors": 1, "error_rate": 0.2, "latency_p50_ms": 4.0,
+                            "latency_p90_ms": 9.0, "latency_p99_ms": 9.9, "latency_max_ms": 10.0,
+                            "in_flight": 1, "active_threads": 1,
+                        }],
+                        "failures": {
+                            "500": {
+                                "count": 1,
+                                "samples": [{
+                                    "request": "GET http://localhost/", "status": "500",
+                                    "headers": { "content-type": "text/plain" }, "message": "<oops>",
+                                }],
+                            },
+                        },
+                        "threads": [{
+                            "thread": 0, "count": 10, "success": 8, "error": 1, "client_error": 1,
+                            "busy_ratio": 0.25,
+                        }],
+                    }],
+                },
+            },
+        }))
+        .unwrap();
+        let out = html(&results).unwrap();
+        assert!(out.contains("Campaign \"&lt;main&gt;\""));
+        assert!(out.contains("<td>Phase #0</td><td>10</td><td>8</td><td>1</td><td>1</td><td>5.00</td><td>4.00</td>"));
+        assert!(out.contains("Throughput (requests / s)"));
+        assert!(out.contains("request error (1, 10.0%)"));
+        assert!(out.contains("<summary>500 (1, 1 sample(s))</summary>"));
+        assert!(out.contains("<pre>content-type: text/plain\n</pre><pre>&lt;oops&gt;</pre>"));
+        assert!(out.contains("<summary>Threads (1)</summary>"));
+        assert!(out.contains("<td>#0</td><td>10</td><td>8</td><td>1</td><td>1</td><td>-</td><td>-</td><td>25.0</td>"));
+        assert!(!out.contains("<script"));
+
+        let mut shared = results.clone();
+        shared.config = serde_json::from_value(serde_json::json!({
+            "<main>": {
+                "phases": [{
+                    "target": { "static": "http://localhost/" }, "threads": 1, "ends": { "requests": 1 },
+                    "timeout": { "s": 1 }, "report": {}, "spec": { "get": { "header": {}, "query": {} } },
+                    "behaviours": { "ok": [], "error": {} },
+                    "auth": { "basic": { "username": { "static": "viking" }, "password": { "static": "hunter2" } } },
+                }],
+            },
+        }))
+        .unwrap();
+        let out = html(&shared).unwrap();
+        assert!(out.contains("<h2>Configuration</h2>"));
+        assert!(out.contains("viking"));
+        assert!(!out.contains("hunter2"));
+
+        let empty = RaidResults {
+            elapsed_ms: 0,
+            campaigns: BTreeMap::from([("empty".to_owned(), CampaignResults {
+                elapsed_ms: 0,
+                phases: Vec::new(),
+            })]),
+            config: BTreeMap::new(),
+        };
+        assert!(html(&empty).is_ok());
+    }
+}
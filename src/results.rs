@@ -0,0 +1,459 @@
+use {
+    crate::{
+        config::Campaign,
+        failures::FailureResults,
+        timeseries::Row,
+    },
+    std::collections::BTreeMap,
+};
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct RaidResults {
+    pub elapsed_ms: u64,
+    pub campaigns: BTreeMap<String, CampaignResults>,
+    /// Configuration of the raided campaigns.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub config: BTreeMap<String, Campaign>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CampaignResults {
+    pub elapsed_ms: u64,
+    pub phases: Vec<PhaseResults>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct PhaseResults {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    pub elapsed_ms: u64,
+    pub count: usize,
+    pub success: usize,
+    pub error: usize,
+    pub client_error: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<LatencyResults>,
+    /// Responses by status, for phases whose responses have a status code.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub statuses: BTreeMap<String, usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub web_socket: Option<WebSocketResults>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<StreamResults>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timing: Option<TimingResults>,
+    /// Counts by GraphQL operation name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub operations: BTreeMap<String, OperationResults>,
+    /// Aggregates per tick of the time series.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub series: Vec<Row>,
+    /// Stats per worker thread.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub threads: Vec<ThreadResults>,
+    /// Requests marked as errors and request errors by status code or kind of
+    /// error, with samples.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub failures: BTreeMap<String, FailureResults>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ThreadResults {
+    pub thread: usize,
+    pub count: usize,
+    pub success: usize,
+    pub error: usize,
+    pub client_error: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<LatencyResults>,
+    /// Share of its lifetime the thread spent on requests, from 0 to 1.
+    pub busy_ratio: f64,
+}
+
+/// Latency percentiles of the requests of a phase.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct LatencyResults {
+    pub count: usize,
+    pub min_ms: f64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+/// Latency distribution with buckets that are about 1% wide, so that
+/// percentiles can be taken from merged distributions.
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    /// Counts by bucket, the index is 100 times the natural logarithm of the
+    /// latency in microseconds.
+    buckets: BTreeMap<u32, usize>,
+    summary: Summary,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct OperationResults {
+    pub count: usize,
+    pub success: usize,
+    pub error: usize,
+    pub client_error: usize,
+}
+
+/// Connection and message stats of a WebSocket phase.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct WebSocketResults {
+    pub connects: usize,
+    pub connect_ms: Summary,
+    pub sent: usize,
+    pub received: usize,
+    /// Time between sending a message and receiving the next one.
+    pub round_trip_ms: Summary,
+    /// Number of closed connections by reason.
+    pub disconnects: BTreeMap<String, usize>,
+}
+
+/// Where the time of HTTP requests is spent. The phases do not overlap.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TimingResults {
+    /// Name lookups, which only happen for new connections.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns: Option<LatencyResults>,
+    /// TCP connects (and proxy tunnels) of new connections.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect: Option<LatencyResults>,
+    /// TLS handshakes of new connections.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<LatencyResults>,
+    /// From sending the request on an open connection to the response
+    /// headers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttfb: Option<LatencyResults>,
+    /// Reading the body.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transfer: Option<LatencyResults>,
+}
+
+/// Distributions behind `TimingResults`.
+#[derive(Debug, Clone, Default)]
+pub struct Timing {
+    pub dns: Histogram,
+    pub connect: Histogram,
+    pub tls: Histogram,
+    pub ttfb: Histogram,
+    pub transfer: Histogram,
+}
+
+/// Event stats of a streaming phase.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct StreamResults {
+    pub opened: usize,
+    /// Time between sending the request and the first byte of the body.
+    pub first_byte_ms: Summary,
+    pub events: usize,
+    /// Time between two events of a stream.
+    pub gap_ms: Summary,
+    /// Time between the response headers and the end of a stream.
+    pub lifetime_ms: Summary,
+    /// Number of ended streams by reason.
+    pub closes: BTreeMap<String, usize>,
+}
+
+/// Count, minimum, mean and maximum of a series of values.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Summary {
+    pub count: usize,
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+}
+
+impl CampaignResults {
+    /// Totals over all phases of the campaign.
+    pub fn combined(&self) -> PhaseResults {
+        PhaseResults {
+            group: None,
+            elapsed_ms: self.elapsed_ms,
+            count: self.phases.iter().map(|v| v.count).sum(),
+            success: self.phases.iter().map(|v| v.success).sum(),
+            error: self.phases.iter().map(|v| v.error).sum(),
+            client_error: self.phases.iter().map(|v| v.client_error).sum(),
+            // percentiles of phases can not be combined, neither can timings
+            latency: None,
+            statuses: self.phases.iter().fold(BTreeMap::new(), |mut acc, v| {
+                for (status, count) in &v.statuses {
+                    *acc.entry(status.clone()).or_default() += count;
+                }
+                acc
+            }),
+            web_socket: WebSocketResults::merged(self.phases.iter().filter_map(|v| v.web_socket.as_ref())),
+            stream: StreamResults::merged(self.phases.iter().filter_map(|v| v.stream.as_ref())),
+            timing: None,
+            operations: OperationResults::merged(self.phases.iter().map(|v| &v.operations)),
+            series: Vec::new(),
+            threads: Vec::new(),
+            failures: BTreeMap::new(),
+        }
+    }
+}
+
+impl PhaseResults {
+    pub fn requests_per_second(&self) -> f64 {
+        if self.elapsed_ms == 0 {
+            return 0.0;
+        }
+        self.count as f64 / (self.elapsed_ms as f64 / 1000.0)
+    }
+}
+
+impl WebSocketResults {
+    /// Merges all results, `None` if there are none.
+    pub fn merged<'a>(results: impl Iterator<Item=&'a Self>) -> Option<Self> {
+        results.fold(None, |acc, v| {
+            let mut acc = acc.unwrap_or_default();
+            acc.merge(v);
+            Some(acc)
+        })
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.connects += other.connects;
+        self.connect_ms.merge(&other.connect_ms);
+        self.sent += other.sent;
+        self.received += other.received;
+        self.round_trip_ms.merge(&other.round_trip_ms);
+        for (reason, count) in &other.disconnects {
+            *self.disconnects.entry(reason.clone()).or_default() += count;
+        }
+    }
+}
+
+impl Timing {
+    /// Merges all distributions, `None` if there are none.
+    pub fn merged<'a>(timings: impl Iterator<Item=&'a Self>) -> Option<Self> {
+        timings.fold(None, |acc, v| {
+            let mut acc = acc.unwrap_or_default();
+            acc.dns.merge(&v.dns);
+            acc.connect.merge(&v.connect);
+            acc.tls.merge(&v.tls);
+            acc.ttfb.merge(&v.ttfb);
+            acc.transfer.merge(&v.transfer);
+            Some(acc)
+        })
+    }
+
+    pub fn results(&self) -> TimingResults {
+        TimingResults {
+            dns: self.dns.results(),
+            connect: self.connect.results(),
+            tls: self.tls.results(),
+            ttfb: self.ttfb.results(),
+            transfer: self.transfer.results(),
+        }
+    }
+}
+
+impl StreamResults {
+    /// Merges all results, `None` if there are none.
+    pub fn merged<'a>(results: impl Iterator<Item=&'a Self>) -> Option<Self> {
+        results.fold(None, |acc, v| {
+            let mut acc = acc.unwrap_or_default();
+            acc.merge(v);
+            Some(acc)
+        })
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.opened += other.opened;
+        self.first_byte_ms.merge(&other.first_byte_ms);
+        self.events += other.events;
+        self.gap_ms.merge(&other.gap_ms);
+        self.lifetime_ms.merge(&other.lifetime_ms);
+        for (reason, count) in &other.closes {
+            *self.closes.entry(reason.clone()).or_default() += count;
+        }
+    }
+}
+
+impl OperationResults {
+    /// Adds up the counts of all maps by operation name.
+    pub fn merged<'a>(maps: impl Iterator<Item=&'a BTreeMap<String, Self>>) -> BTreeMap<String, Self> {
+        let mut merged = BTreeMap::<String, Self>::new();
+        for (name, v) in maps.flatten() {
+            let entry = merged.entry(name.clone()).or_default();
+            entry.count += v.count;
+            entry.success += v.success;
+            entry.error += v.error;
+            entry.client_error += v.client_error;
+        }
+        merged
+    }
+}
+
+impl Histogram {
+    pub fn add(&mut self, ms: f64) {
+        let bucket = ((ms * 1000.0).max(1.0).ln() * 100.0).round() as u32;
+        *self.buckets.entry(bucket).or_default() += 1;
+        self.summary.add(ms);
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        for (bucket, count) in &other.buckets {
+            *self.buckets.entry(*bucket).or_default() += count;
+        }
+        self.summary.merge(&other.summary);
+    }
+
+    /// Merges all distributions.
+    pub fn merged<'a>(histograms: impl Iterator<Item=&'a Self>) -> Self {
+        histograms.fold(Self::default(), |mut acc, v| {
+            acc.merge(v);
+            acc
+        })
+    }
+
+    pub fn summary(&self) -> &Summary {
+        &self.summary
+    }
+
+    /// The latency at quantile `q` (nearest rank), in milliseconds.
+    pub fn quantile(&self, q: f64) -> f64 {
+        let rank = ((q * self.summary.count as f64).ceil() as usize).max(1);
+        let mut seen = 0;
+        for (bucket, count) in &self.buckets {
+            seen += count;
+            if seen >= rank {
+                let ms = (*bucket as f64 / 100.0).exp() / 1000.0;
+                return ms.clamp(self.summary.min, self.summary.max);
+            }
+        }
+        self.summary.max
+    }
+
+    /// Percentiles of the distribution, `None` if it is empty.
+    pub fn results(&self) -> Option<LatencyResults> {
+        if self.summary.count == 0 {
+            return None;
+        }
+        Some(LatencyResults {
+            count: self.summary.count,
+            min_ms: self.summary.min,
+            mean_ms: self.summary.mean,
+            p50_ms: self.quantile(0.5),
+            p90_ms: self.quantile(0.9),
+            p95_ms: self.quantile(0.95),
+            p99_ms: self.quantile(0.99),
+            max_ms: self.summary.max,
+        })
+    }
+}
+
+impl Summary {
+    pub fn add(&mut self, value: f64) {
+        self.merge(&Self {
+            count: 1,
+            min: value,
+            mean: value,
+            max: value,
+        });
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = other.clone();
+            return;
+        }
+        let count = self.count + other.count;
+        self.mean = (self.mean * self.count as f64 + other.mean * other.count as f64) / count as f64;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.count = count;
+    }
+}
+
+impl std::fmt::Display for LatencyResults {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "p50 {:.2} / p90 {:.2} / p99 {:.2} / max {:.2}",
+            self.p50_ms, self.p90_ms, self.p99_ms, self.max_ms
+        )
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "min {:.2} / avg {:.2} / max {:.2}", self.min, self.mean, self.max)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut a = Histogram::default();
+        let mut b = Histogram::default();
+        for ms in 1..=1000 {
+            if ms % 2 == 0 {
+                a.add(ms as f64)
+            } else {
+                b.add(ms as f64)
+            }
+        }
+        assert!(Histogram::default().results().is_none());
+        let latency = Histogram::merged([a, b].iter()).results().unwrap();
+        assert_eq!(latency.count, 1000);
+        assert_eq!(latency.min_ms, 1.0);
+        assert_eq!(latency.max_ms, 1000.0);
+        // buckets are about 1% wide
+        assert!((latency.p50_ms - 500.0).abs() < 5.0);
+        assert!((latency.p99_ms - 990.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn test_timing() {
+        let mut a = Timing::default();
+        let mut b = Timing::default();
+        for ms in 1..=100 {
+            let timing = if ms % 2 == 0 { &mut a } else { &mut b };
+            timing.ttfb.add(ms as f64);
+            timing.transfer.add(1.0);
+        }
+        a.dns.add(5.0);
+        a.connect.add(2.0);
+        b.connect.add(3.0);
+        assert!(Timing::merged(std::iter::empty()).is_none());
+        let timing = Timing::merged([a, b].iter()).unwrap().results();
+        let dns = timing.dns.unwrap();
+        assert_eq!((dns.count, dns.p99_ms), (1, 5.0));
+        assert_eq!(timing.connect.unwrap().count, 2);
+        assert!(timing.tls.is_none());
+        let ttfb = timing.ttfb.unwrap();
+        assert_eq!(ttfb.count, 100);
+        assert!((ttfb.p90_ms - 90.0).abs() < 1.0);
+        assert_eq!(timing.transfer.unwrap().p50_ms, 1.0);
+        assert_eq!(
+            ttfb.to_string(),
+            format!(
+                "p50 {:.2} / p90 {:.2} / p99 {:.2} / max 100.00",
+                ttfb.p50_ms, ttfb.p90_ms, ttfb.p99_ms
+            )
+        );
+    }
+}
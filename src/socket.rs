@@ -0,0 +1,483 @@
+use {
+    crate::{
+        config::{
+            ErrorBehaviour,
+            Payload,
+            Read,
+        },
+        engine::{
+            Reporter,
+            ThreadEvent,
+        },
+        error::Error,
+        failures::Sample,
+        template::Templates,
+    },
+    anyhow::Result,
+    std::{
+        io::{
+            self,
+            ErrorKind,
+            Write,
+        },
+        net::{
+            SocketAddr,
+            TcpStream,
+            ToSocketAddrs,
+            UdpSocket,
+        },
+        sync::Arc,
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+/// The payload templates of a phase. Hex payloads are decoded after they are
+/// rendered.
+pub struct Payloads {
+    templates: Templates,
+    hex: Vec<bool>,
+}
+
+impl Payloads {
+    pub fn new(payloads: &[Payload]) -> Result<Self> {
+        if payloads.is_empty() {
+            return Err(Error::Config("socket spec without payloads".into()).into());
+        }
+        let (templates, hex): (Vec<_>, Vec<_>) = payloads
+            .iter()
+            .map(|v| {
+                match v {
+                    | Payload::Text(v) => (v.clone(), false),
+                    | Payload::Hex(v) => (v.clone(), true),
+                }
+            })
+            .unzip();
+        let payloads = Self {
+            templates: Templates::new(&templates)?,
+            hex,
+        };
+        // fail before the phase starts on invalid hex
+        for idx in 0..templates.len() {
+            payloads.render(idx)?;
+        }
+        Ok(payloads)
+    }
+
+    pub fn render(&self, sequence: usize) -> Result<Bytes> {
+        let rendered = self.templates.render(sequence)?;
+        match self.hex[sequence % self.hex.len()] {
+            | true => decode_hex(&rendered).map(Bytes),
+            | false => Ok(Bytes(rendered.into_bytes())),
+        }
+    }
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>> {
+    let digits = value.chars().filter(|v| !v.is_whitespace()).collect::<Vec<_>>();
+    if digits.len() % 2 != 0 {
+        return Err(Error::Config(format!("odd number of hex digits in {}", value)).into());
+    }
+    digits
+        .chunks(2)
+        .map(|v| {
+            u8::from_str_radix(&v.iter().collect::<String>(), 16)
+                .map_err(|_| Error::Config(format!("invalid hex payload {}", value)).into())
+        })
+        .collect()
+}
+
+/// A payload to send. Shown as text if it is valid UTF-8 and as hex otherwise.
+pub struct Bytes(pub Vec<u8>);
+
+impl std::fmt::Debug for Bytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match std::str::from_utf8(&self.0) {
+            | Ok(v) => write!(f, "{:?}", v),
+            | Err(_) => {
+                for v in &self.0 {
+                    write!(f, "{:02x}", v)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// The socket endpoint of a phase, resolved once per phase.
+pub struct Endpoint {
+    pub protocol: Protocol,
+    pub addr: SocketAddr,
+    pub read: Option<Read>,
+    /// Timeout for connecting, sending and reading a whole response.
+    pub timeout: Duration,
+    pub keep_alive: bool,
+}
+
+impl Endpoint {
+    /// Resolves `target` (`host:port`, optionally prefixed with `tcp://` or
+    /// `udp://`).
+    pub fn new(
+        protocol: Protocol,
+        target: &str,
+        read: Option<Read>,
+        timeout: Duration,
+        keep_alive: bool,
+    ) -> Result<Self> {
+        let host = target.trim_start_matches("tcp://").trim_start_matches("udp://");
+        let addr = host
+            .to_socket_addrs()
+            .map_err(|e| Error::Config(format!("can not resolve {} ({})", target, e)))?
+            .next()
+            .ok_or_else(|| Error::Config(format!("can not resolve {}", target)))?;
+        if let Some(Read::Delimiter(v)) = &read {
+            if v.is_empty() {
+                return Err(Error::Config("empty read delimiter".into()).into());
+            }
+        }
+        Ok(Self {
+            protocol,
+            addr,
+            read,
+            timeout,
+            keep_alive,
+        })
+    }
+}
+
+pub(crate) enum Socket {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+}
+
+/// An open socket and the bytes it received after the end of the last
+/// response, which belong to the next one.
+pub(crate) struct Connection {
+    socket: Socket,
+    pending: Vec<u8>,
+}
+
+impl Connection {
+    fn send(&mut self, payload: &[u8]) -> io::Result<()> {
+        match &mut self.socket {
+            | Socket::Tcp(v) => v.write_all(payload),
+            | Socket::Udp(v) => v.send(payload).map(|_| ()),
+        }
+    }
+
+    fn receive(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        match &mut self.socket {
+            | Socket::Tcp(v) => {
+                v.set_read_timeout(Some(timeout))?;
+                io::Read::read(v, buf)
+            },
+            | Socket::Udp(v) => {
+                v.set_read_timeout(Some(timeout))?;
+                v.recv(buf)
+            },
+        }
+    }
+}
+
+/// A worker thread (virtual user) of a TCP or UDP phase. It connects lazily,
+/// sends the payloads it receives from the producer and reads a response
+/// after each one. After an error the connection is dropped and opened again
+/// with the next payload.
+pub(crate) struct Worker {
+    pub reporter: Reporter,
+    pub endpoint: Arc<Endpoint>,
+    pub on_error: ErrorBehaviour,
+    pub connection: Option<Connection>,
+}
+
+impl Worker {
+    pub fn run(mut self, tasks: flume::Receiver<Result<Bytes>>) {
+        for payload in tasks.iter() {
+            self.reporter.dequeued();
+            let payload = match payload {
+                | Ok(v) => v,
+                | Err(e) => {
+                    self.reporter.unrendered(self.endpoint.addr.to_string(), &e);
+                    continue;
+                },
+            };
+            let start = Instant::now();
+            match self.exchange(&payload.0) {
+                | Ok(v) => {
+                    self.reporter.report(ThreadEvent::Success {
+                        status: String::from_utf8_lossy(&v).into_owned(),
+                        response: Some(v),
+                        operation: None,
+                        latency: start.elapsed(),
+                        sample: None,
+                    })
+                },
+                | Err(e) => {
+                    self.connection = None;
+                    self.reporter.report(ThreadEvent::Error {
+                        operation: None,
+                        latency: start.elapsed(),
+                        sample: Some(Box::new(Sample::error(self.endpoint.addr.to_string(), &e))),
+                    });
+                    if let Some(v) = &self.on_error.backoff {
+                        std::thread::sleep(Duration::from_millis(v.to_ms()));
+                    }
+                },
+            }
+            if !self.endpoint.keep_alive {
+                self.connection = None;
+            }
+        }
+        self.reporter.report(ThreadEvent::Finished);
+    }
+
+    /// Sends `payload` and returns the response, empty if the phase does not
+    /// read responses.
+    fn exchange(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+        let deadline = Instant::now() + self.endpoint.timeout;
+        self.connect()?;
+        let connection = self.connection.as_mut().unwrap();
+        connection.send(payload)?;
+        match &self.endpoint.read {
+            | Some(v) => Ok(read(connection, v, deadline)?),
+            | None => Ok(Vec::new()),
+        }
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        if self.connection.is_some() {
+            return Ok(());
+        }
+        let addr = &self.endpoint.addr;
+        let connection = match self.endpoint.protocol {
+            | Protocol::Tcp => {
+                let stream = TcpStream::connect_timeout(addr, self.endpoint.timeout)?;
+                stream.set_write_timeout(Some(self.endpoint.timeout))?;
+                stream.set_nodelay(true)?;
+                Socket::Tcp(stream)
+            },
+            | Protocol::Udp => {
+                let local: SocketAddr = match addr {
+                    | SocketAddr::V4(_) => "0.0.0.0:0".parse()?,
+                    | SocketAddr::V6(_) => "[::]:0".parse()?,
+                };
+                let socket = UdpSocket::bind(local)?;
+                socket.connect(addr)?;
+                Socket::Udp(socket)
+            },
+        };
+        self.connection = Some(Connection {
+            socket: connection,
+            pending: Vec::new(),
+        });
+        Ok(())
+    }
+}
+
+/// Reads a response as configured by `until`, failing if it is not complete
+/// by `deadline`. Bytes received after the end of the response are kept for
+/// the next one.
+fn read(connection: &mut Connection, until: &Read, deadline: Instant) -> io::Result<Vec<u8>> {
+    let mut response = std::mem::take(&mut connection.pending);
+    let mut buf = [0_u8; 4096];
+    loop {
+        if let Some(end) = response_end(&response, until) {
+            connection.pending = response.split_off(end);
+            return Ok(response);
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return match until {
+                | Read::Idle(_) => Ok(response),
+                | _ => Err(ErrorKind::TimedOut.into()),
+            };
+        }
+        let wait = match until {
+            | Read::Idle(v) => remaining.min(Duration::from_millis(v.to_ms().max(1))),
+            | _ => remaining,
+        };
+        let n = match connection.receive(&mut buf, wait) {
+            | Ok(0) => {
+                return match until {
+                    | Read::Idle(_) => Ok(response),
+                    | _ => Err(ErrorKind::UnexpectedEof.into()),
+                }
+            },
+            | Ok(v) => v,
+            | Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                match until {
+                    | Read::Idle(_) => return Ok(response),
+                    | _ => continue,
+                }
+            },
+            | Err(e) => return Err(e),
+        };
+        response.extend_from_slice(&buf[..n]);
+    }
+}
+
+/// Where the response in `received` ends, once it is complete. Responses read
+/// until the connection is idle end with the read.
+fn response_end(received: &[u8], until: &Read) -> Option<usize> {
+    match until {
+        | Read::Delimiter(v) => {
+            let delimiter = v.as_bytes();
+            received
+                .windows(delimiter.len())
+                .position(|w| w == delimiter)
+                .map(|pos| pos + delimiter.len())
+        },
+        | Read::Length(v) => (received.len() >= *v).then_some(*v),
+        | Read::Idle(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        std::{
+            io::{
+                BufRead,
+                BufReader,
+            },
+            net::TcpListener,
+        },
+    };
+
+    /// Accepts a single connection and answers every line with `pong <line>`.
+    fn line_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = format!("tcp://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            for line in BufReader::new(stream).lines() {
+                writer
+                    .write_all(format!("pong {}\n", line.unwrap()).as_bytes())
+                    .unwrap();
+            }
+        });
+        target
+    }
+
+    /// Accepts a single connection and answers the first line with two
+    /// responses at once.
+    fn split_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = format!("tcp://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut lines = BufReader::new(stream).lines();
+            lines.next().unwrap().unwrap();
+            writer.write_all(b"one\ntwo\n").unwrap();
+            for _ in lines {}
+        });
+        target
+    }
+
+    /// Echoes every datagram.
+    fn udp_server() -> String {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = socket.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            let mut buf = [0_u8; 1024];
+            loop {
+                let (n, peer) = socket.recv_from(&mut buf).unwrap();
+                socket.send_to(&buf[..n], peer).unwrap();
+            }
+        });
+        target
+    }
+
+    fn worker(protocol: Protocol, target: &str, read: Read) -> Worker {
+        worker_with(protocol, target, read, flume::unbounded().0)
+    }
+
+    fn worker_with(
+        protocol: Protocol,
+        target: &str,
+        read: Read,
+        status_tx: flume::Sender<(usize, usize, ThreadEvent)>,
+    ) -> Worker {
+        let endpoint = Endpoint::new(protocol, target, Some(read), Duration::from_secs(5), true).unwrap();
+        Worker {
+            reporter: Reporter::new(0, 0, status_tx, Default::default()),
+            endpoint: Arc::new(endpoint),
+            on_error: ErrorBehaviour { backoff: None },
+            connection: None,
+        }
+    }
+
+    #[test]
+    fn test_payloads() {
+        let payloads = Payloads::new(&[
+            Payload::Text("ping {{sequence}}\n".into()),
+            Payload::Hex("ff {{sequence}}0".into()),
+        ])
+        .unwrap();
+        assert_eq!(payloads.render(2).unwrap().0, b"ping 2\n");
+        assert_eq!(payloads.render(3).unwrap().0, vec![0xff, 0x30]);
+        assert_eq!(format!("{:?}", payloads.render(1).unwrap()), "ff10");
+        // odd number of digits from sequence 10 on
+        assert!(payloads.render(11).is_err());
+        assert!(Payloads::new(&[Payload::Hex("abc".into())]).is_err());
+    }
+
+    #[test]
+    fn test_exchange() {
+        let mut tcp = worker(Protocol::Tcp, &line_server(), Read::Delimiter("\n".into()));
+        assert_eq!(tcp.exchange(b"a\n").unwrap(), b"pong a\n");
+        assert_eq!(tcp.exchange(b"b\n").unwrap(), b"pong b\n");
+        let mut tcp = worker(Protocol::Tcp, &line_server(), Read::Length(4));
+        assert_eq!(tcp.exchange(b"c\n").unwrap(), b"pong");
+
+        let mut udp = worker(
+            Protocol::Udp,
+            &udp_server(),
+            Read::Idle(crate::config::Duration::MilliSeconds(100)),
+        );
+        assert_eq!(udp.exchange(b"x").unwrap(), b"x");
+        assert_eq!(udp.exchange(b"yz").unwrap(), b"yz");
+    }
+
+    #[test]
+    fn test_pending() {
+        let mut tcp = worker(Protocol::Tcp, &split_server(), Read::Delimiter("\n".into()));
+        assert_eq!(tcp.exchange(b"a\n").unwrap(), b"one\n");
+        assert_eq!(tcp.exchange(b"b\n").unwrap(), b"two\n");
+    }
+
+    #[test]
+    fn test_run() {
+        let payloads = Payloads::new(&[Payload::Hex("0{{sequence}}0a".into())]).unwrap();
+        let (status_tx, status_rx) = flume::unbounded();
+        let worker = worker_with(Protocol::Tcp, &line_server(), Read::Delimiter("\n".into()), status_tx);
+        let (tasks_tx, tasks_rx) = flume::unbounded();
+        for idx in [9, 10] {
+            tasks_tx.send(payloads.render(idx)).unwrap();
+        }
+        drop(tasks_tx);
+        worker.run(tasks_rx);
+
+        let events = status_rx.drain().map(|v| v.2).collect::<Vec<_>>();
+        match &events[0] {
+            | ThreadEvent::Success { response, .. } => assert_eq!(response.as_deref(), Some(&b"pong \x09\n"[..])),
+            | _ => panic!("expected a response"),
+        }
+        match &events[1] {
+            | ThreadEvent::Unrendered { sample } => assert!(sample.message.contains("odd number")),
+            | _ => panic!("expected an unrendered request"),
+        }
+        assert!(matches!(events[2], ThreadEvent::Finished));
+    }
+}
@@ -0,0 +1,203 @@
+use {
+    crate::{
+        config::StreamEvents,
+        engine::{
+            Reporter,
+            ThreadEvent,
+        },
+        results::StreamResults,
+    },
+    std::{
+        io::{
+            ErrorKind,
+            Read,
+        },
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+};
+
+/// Events of a streaming worker thread.
+#[derive(Debug)]
+pub(crate) enum Event {
+    Opened,
+    FirstByte(Duration),
+    /// An event of the stream, with the time since the previous one.
+    Received {
+        gap: Option<Duration>,
+    },
+    Closed {
+        lifetime: Duration,
+        reason: String,
+    },
+}
+
+impl Event {
+    pub fn record(self, stats: &mut StreamResults) {
+        match self {
+            | Self::Opened => stats.opened += 1,
+            | Self::FirstByte(v) => stats.first_byte_ms.add(v.as_secs_f64() * 1000.0),
+            | Self::Received { gap } => {
+                stats.events += 1;
+                if let Some(v) = gap {
+                    stats.gap_ms.add(v.as_secs_f64() * 1000.0);
+                }
+            },
+            | Self::Closed { lifetime, reason } => {
+                stats.lifetime_ms.add(lifetime.as_secs_f64() * 1000.0);
+                *stats.closes.entry(reason).or_default() += 1;
+            },
+        }
+    }
+}
+
+/// The stream settings of a phase.
+pub struct Stream {
+    pub events: StreamEvents,
+    pub lifetime: Option<Duration>,
+}
+
+impl Stream {
+    /// Reads `body` until it ends, fails or outlives the lifetime of the
+    /// stream and reports its events. `start` is when the request was sent.
+    /// Reads time out after the client timeout of the phase.
+    pub fn consume(&self, mut body: impl Read, start: Instant, reporter: &Reporter) {
+        let opened = Instant::now();
+        reporter.report(ThreadEvent::Stream(Event::Opened));
+        let mut parser = Parser::new(&self.events);
+        let mut last_event = None::<Instant>;
+        let mut first_byte = true;
+        let mut buf = [0_u8; 8192];
+        let reason = loop {
+            if self.lifetime.is_some_and(|v| opened.elapsed() >= v) {
+                break "lifetime".to_owned();
+            }
+            let n = match body.read(&mut buf) {
+                | Ok(0) => break "closed by server".to_owned(),
+                | Ok(v) => v,
+                | Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                | Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    break "timeout".to_owned()
+                },
+                | Err(e) => break format!("io error ({})", e.kind()),
+            };
+            if first_byte {
+                first_byte = false;
+                reporter.report(ThreadEvent::Stream(Event::FirstByte(start.elapsed())));
+            }
+            for _ in 0..parser.feed(&buf[..n]) {
+                let now = Instant::now();
+                let gap = last_event.map(|v| now - v);
+                last_event = Some(now);
+                reporter.report(ThreadEvent::Stream(Event::Received { gap }));
+            }
+        };
+        reporter.report(ThreadEvent::Stream(Event::Closed {
+            lifetime: opened.elapsed(),
+            reason,
+        }));
+    }
+}
+
+/// Splits a body into lines and counts the events they make up.
+struct Parser {
+    sse: bool,
+    line: Vec<u8>,
+    /// Whether the last byte was a `\r`, so that a following `\n` is skipped.
+    carriage_return: bool,
+    /// Whether the current SSE event has data and gets dispatched at the next
+    /// blank line.
+    data: bool,
+}
+
+impl Parser {
+    fn new(events: &StreamEvents) -> Self {
+        Self {
+            sse: matches!(events, StreamEvents::Sse),
+            line: Vec::new(),
+            carriage_return: false,
+            data: false,
+        }
+    }
+
+    /// Returns the number of events completed by `bytes`.
+    fn feed(&mut self, bytes: &[u8]) -> usize {
+        let mut events = 0;
+        for &b in bytes {
+            let skip = self.carriage_return && b == b'\n';
+            self.carriage_return = b == b'\r';
+            if skip {
+                continue;
+            }
+            if b != b'\n' && b != b'\r' {
+                self.line.push(b);
+                continue;
+            }
+            if self.line_ended() {
+                events += 1;
+            }
+            self.line.clear();
+        }
+        events
+    }
+
+    fn line_ended(&mut self) -> bool {
+        if !self.sse {
+            return !self.line.is_empty();
+        }
+        if self.line.is_empty() {
+            return std::mem::take(&mut self.data);
+        }
+        // SSE: `data` fields, with or without a value, make up an event
+        if self.line == b"data" || self.line.starts_with(b"data:") {
+            self.data = true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parser() {
+        let mut sse = Parser::new(&StreamEvents::Sse);
+        assert_eq!(sse.feed(b": comment\n\nevent: a\ndata: 1\n\ndata: 2\r\n"), 1);
+        assert_eq!(sse.feed(b"data: 3\r"), 0);
+        assert_eq!(sse.feed(b"\n\r\nevent: b\n\nid: 4\ndata\n\n"), 2);
+
+        let mut lines = Parser::new(&StreamEvents::Lines);
+        assert_eq!(lines.feed(b"{\"a\": 1}\n\n{\"a\""), 1);
+        assert_eq!(lines.feed(b": 2}\r\n"), 1);
+    }
+
+    #[test]
+    fn test_consume() {
+        let (status_tx, status_rx) = flume::unbounded();
+        let stream = Stream {
+            events: StreamEvents::Sse,
+            lifetime: None,
+        };
+        let body: &[u8] = b"data: a\n\ndata: b\n\ndata: c\n\n";
+        stream.consume(
+            body,
+            Instant::now(),
+            &Reporter::new(0, 0, status_tx, Default::default()),
+        );
+
+        let mut stats = StreamResults::default();
+        for (_, _, event) in status_rx.drain() {
+            if let ThreadEvent::Stream(v) = event {
+                v.record(&mut stats);
+            }
+        }
+        assert_eq!(stats.opened, 1);
+        assert_eq!(stats.first_byte_ms.count, 1);
+        assert_eq!(stats.events, 3);
+        assert_eq!(stats.gap_ms.count, 2);
+        assert_eq!(stats.closes.get("closed by server"), Some(&1));
+    }
+}
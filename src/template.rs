@@ -0,0 +1,58 @@
+use {
+    anyhow::Result,
+    handlebars::Handlebars,
+    std::time::{
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
+/// Payload templates of a phase (messages, bodies, variables). Templates are
+/// rendered with handlebars and can use
+///
+/// - `{{sequence}}`: index of the request within the phase
+/// - `{{timestamp}}`: unix time in milliseconds
+pub struct Templates {
+    registry: Handlebars<'static>,
+    count: usize,
+}
+
+#[derive(serde::Serialize)]
+struct Context {
+    sequence: usize,
+    timestamp: u128,
+}
+
+impl Templates {
+    pub fn new(templates: &[String]) -> Result<Self> {
+        let mut registry = Handlebars::new();
+        registry.set_strict_mode(true);
+        registry.register_escape_fn(handlebars::no_escape);
+        for (idx, template) in templates.iter().enumerate() {
+            registry.register_template_string(&idx.to_string(), template)?;
+        }
+        let templates = Self {
+            registry,
+            count: templates.len(),
+        };
+        // fail before the phase starts if a template uses unknown variables
+        for idx in 0..templates.count {
+            templates.render(idx)?;
+        }
+        Ok(templates)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Renders the template for the request `sequence`. Templates are used in
+    /// turn, starting over after the last one.
+    pub fn render(&self, sequence: usize) -> Result<String> {
+        let context = Context {
+            sequence,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
+        };
+        Ok(self.registry.render(&(sequence % self.count).to_string(), &context)?)
+    }
+}
@@ -0,0 +1,244 @@
+use {
+    crate::results::Histogram,
+    anyhow::Result,
+    std::{
+        fs::File,
+        io::{
+            BufWriter,
+            Write,
+        },
+        path::Path,
+        sync::Mutex,
+        time::{
+            Duration,
+            SystemTime,
+            UNIX_EPOCH,
+        },
+    },
+};
+
+/// Tick of the time series for phases without a report interval.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+const COLUMNS: &[&str] = &[
+    "timestamp_ms",
+    "campaign",
+    "phase",
+    "elapsed_ms",
+    "requests",
+    "rps",
+    "errors",
+    "error_rate",
+    "latency_p50_ms",
+    "latency_p90_ms",
+    "latency_p99_ms",
+    "latency_max_ms",
+    "in_flight",
+    "active_threads",
+];
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Csv,
+    Jsonl,
+}
+
+/// Aggregates of a phase over one tick of the time series.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Row {
+    pub timestamp_ms: u64,
+    pub campaign: String,
+    pub phase: usize,
+    /// Time since the phase started.
+    pub elapsed_ms: u64,
+    pub requests: usize,
+    pub rps: f64,
+    /// Requests marked as errors and request errors.
+    pub errors: usize,
+    pub error_rate: f64,
+    pub latency_p50_ms: Option<f64>,
+    pub latency_p90_ms: Option<f64>,
+    pub latency_p99_ms: Option<f64>,
+    pub latency_max_ms: Option<f64>,
+    pub in_flight: usize,
+    pub active_threads: usize,
+}
+
+/// Time series file of a raid, shared by all campaigns. Files ending in
+/// `.csv` are written as CSV, all others as JSON lines.
+#[derive(Debug)]
+pub struct Writer {
+    file: Mutex<BufWriter<File>>,
+    format: Format,
+}
+
+impl Writer {
+    pub fn create(path: &str) -> Result<Self> {
+        let format = match Path::new(path).extension().and_then(|v| v.to_str()) {
+            | Some(v) if v.eq_ignore_ascii_case("csv") => Format::Csv,
+            | _ => Format::Jsonl,
+        };
+        let mut file = BufWriter::new(File::create(path)?);
+        if let Format::Csv = format {
+            writeln!(file, "{}", COLUMNS.join(","))?;
+            file.flush()?;
+        }
+        Ok(Self {
+            file: Mutex::new(file),
+            format,
+        })
+    }
+
+    /// Writes the rows of a tick.
+    pub fn write(&self, rows: &[Row]) -> Result<()> {
+        let mut file = self.file.lock().unwrap();
+        for row in rows {
+            match self.format {
+                | Format::Csv => {
+                    let opt = |v: Option<f64>| v.map(|v| format!("{:.3}", v)).unwrap_or_default();
+                    writeln!(
+                        file,
+                        "{},{},{},{},{},{:.3},{},{:.4},{},{},{},{},{},{}",
+                        row.timestamp_ms,
+                        csv_field(&row.campaign),
+                        row.phase,
+                        row.elapsed_ms,
+                        row.requests,
+                        row.rps,
+                        row.errors,
+                        row.error_rate,
+                        opt(row.latency_p50_ms),
+                        opt(row.latency_p90_ms),
+                        opt(row.latency_p99_ms),
+                        opt(row.latency_max_ms),
+                        row.in_flight,
+                        row.active_threads
+                    )?;
+                },
+                | Format::Jsonl => writeln!(file, "{}", serde_json::to_string(row)?)?,
+            }
+        }
+        // flushed per tick so that the file can be followed while raiding
+        file.flush()?;
+        Ok(())
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Requests of a phase since the last tick.
+#[derive(Debug, Default)]
+pub struct Window {
+    requests: usize,
+    latency: Histogram,
+    errors: usize,
+}
+
+impl Window {
+    pub fn add(&mut self, latency: Duration, error: bool) {
+        self.count(error);
+        self.latency.add(latency.as_secs_f64() * 1000.0);
+    }
+
+    /// Counts a request that has no latency, e.g. one that was never sent.
+    pub fn count(&mut self, error: bool) {
+        self.requests += 1;
+        if error {
+            self.errors += 1;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests == 0
+    }
+
+    /// Turns the window into a row and starts a new one. `length` is the time
+    /// since the last tick.
+    pub fn take(&mut self, campaign: &str, phase: usize, elapsed: Duration, length: Duration) -> Row {
+        let Self {
+            requests,
+            latency,
+            errors,
+        } = std::mem::take(self);
+        let latency = latency.results();
+        Row {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            campaign: campaign.to_owned(),
+            phase,
+            elapsed_ms: elapsed.as_millis() as u64,
+            requests,
+            rps: requests as f64 / length.as_secs_f64().max(0.001),
+            errors,
+            error_rate: if requests == 0 {
+                0.0
+            } else {
+                errors as f64 / requests as f64
+            },
+            latency_p50_ms: latency.as_ref().map(|v| v.p50_ms),
+            latency_p90_ms: latency.as_ref().map(|v| v.p90_ms),
+            latency_p99_ms: latency.as_ref().map(|v| v.p99_ms),
+            latency_max_ms: latency.as_ref().map(|v| v.max_ms),
+            in_flight: 0,
+            active_threads: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_window() {
+        let mut window = Window::default();
+        for ms in 1..=100 {
+            window.add(Duration::from_millis(ms), ms > 90);
+        }
+        let row = window.take("main", 0, Duration::from_secs(3), Duration::from_secs(2));
+        assert_eq!(row.requests, 100);
+        assert_eq!(row.rps, 50.0);
+        assert_eq!(row.errors, 10);
+        assert_eq!(row.error_rate, 0.1);
+        // percentiles come from buckets about 1% wide
+        assert!((row.latency_p50_ms.unwrap() - 50.0).abs() < 0.5);
+        assert!((row.latency_p99_ms.unwrap() - 99.0).abs() < 1.0);
+        assert_eq!(row.latency_max_ms, Some(100.0));
+        assert!(window.is_empty());
+
+        let row = window.take("main", 0, Duration::from_secs(4), Duration::from_secs(1));
+        assert_eq!(row.requests, 0);
+        assert_eq!(row.latency_p50_ms, None);
+
+        // requests without a latency count, but leave the percentiles alone
+        window.add(Duration::from_millis(10), false);
+        window.count(true);
+        let row = window.take("main", 0, Duration::from_secs(5), Duration::from_secs(1));
+        assert_eq!((row.requests, row.errors), (2, 1));
+        assert_eq!((row.latency_p50_ms, row.latency_max_ms), (Some(10.0), Some(10.0)));
+    }
+
+    #[test]
+    fn test_writer() {
+        let path = std::env::temp_dir().join(format!("viking-timeseries-{}.csv", std::process::id()));
+        let writer = Writer::create(path.to_str().unwrap()).unwrap();
+        let mut window = Window::default();
+        window.add(Duration::from_millis(20), false);
+        writer
+            .write(&[window.take("a,b", 1, Duration::from_secs(1), Duration::from_secs(1))])
+            .unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], COLUMNS.join(","));
+        assert!(lines[1].ends_with(",\"a,b\",1,1000,1,1.000,0,0.0000,20.000,20.000,20.000,20.000,0,0"));
+    }
+}
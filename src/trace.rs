@@ -0,0 +1,129 @@
+use {
+    crate::{
+        config::{
+            self,
+            TraceScope,
+        },
+        error::Error,
+    },
+    anyhow::Result,
+    rand::Rng,
+    reqwest::header::{
+        HeaderMap,
+        HeaderValue,
+    },
+};
+
+/// Trace context of a single request. The IDs are lowercase hex, as in the
+/// `traceparent` header.
+#[derive(Debug, Clone)]
+pub struct Context {
+    pub trace_id: String,
+    pub span_id: String,
+    sampled: bool,
+    state: Option<HeaderValue>,
+}
+
+impl Context {
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{:02x}", self.trace_id, self.span_id, u8::from(self.sampled))
+    }
+
+    pub fn apply(&self, header: &mut HeaderMap) {
+        header.insert("traceparent", HeaderValue::from_str(&self.traceparent()).unwrap());
+        if let Some(v) = &self.state {
+            header.insert("tracestate", v.clone());
+        }
+    }
+}
+
+/// Creates the trace contexts of a worker thread.
+#[derive(Clone)]
+pub struct Tracer {
+    scope: TraceScope,
+    sampled: bool,
+    state: Option<HeaderValue>,
+    /// Trace of the current session, for the session scope.
+    trace_id: Option<String>,
+}
+
+impl Tracer {
+    pub fn new(config: &config::Trace) -> Result<Self> {
+        let state = match &config.state {
+            | Some(v) => {
+                Some(HeaderValue::from_str(v).map_err(|_| Error::Config(format!("invalid tracestate {}", v)))?)
+            },
+            | None => None,
+        };
+        Ok(Self {
+            scope: config.scope.clone().unwrap_or(TraceScope::Request),
+            sampled: config.sampled.unwrap_or(true),
+            state,
+            trace_id: None,
+        })
+    }
+
+    /// Ends the trace of the current session.
+    pub fn begin_session(&mut self) {
+        self.trace_id = None;
+    }
+
+    /// The context of the next request, with a new span.
+    pub fn next(&mut self) -> Context {
+        let trace_id = match self.scope {
+            | TraceScope::Request => random_id::<16>(),
+            | TraceScope::Session => self.trace_id.get_or_insert_with(random_id::<16>).clone(),
+        };
+        Context {
+            trace_id,
+            span_id: random_id::<8>(),
+            sampled: self.sampled,
+            state: self.state.clone(),
+        }
+    }
+}
+
+/// A random, non-zero ID of `N` bytes in hex.
+fn random_id<const N: usize>() -> String {
+    let mut bytes = [0_u8; N];
+    while bytes.iter().all(|v| *v == 0) {
+        rand::thread_rng().fill(&mut bytes[..]);
+    }
+    bytes.iter().map(|v| format!("{:02x}", v)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tracer() {
+        let config = |scope| {
+            config::Trace {
+                scope: Some(scope),
+                state: Some("vendor=viking".into()),
+                sampled: None,
+            }
+        };
+
+        let mut tracer = Tracer::new(&config(TraceScope::Request)).unwrap();
+        let (a, b) = (tracer.next(), tracer.next());
+        assert_ne!(a.trace_id, b.trace_id);
+        assert_eq!(a.trace_id.len(), 32);
+        assert_eq!(a.span_id.len(), 16);
+        let mut header = HeaderMap::new();
+        a.apply(&mut header);
+        assert_eq!(
+            header["traceparent"],
+            format!("00-{}-{}-01", a.trace_id, a.span_id).as_str()
+        );
+        assert_eq!(header["tracestate"], "vendor=viking");
+
+        let mut tracer = Tracer::new(&config(TraceScope::Session)).unwrap();
+        let (a, b) = (tracer.next(), tracer.next());
+        assert_eq!(a.trace_id, b.trace_id);
+        assert_ne!(a.span_id, b.span_id);
+        tracer.begin_session();
+        assert_ne!(tracer.next().trace_id, a.trace_id);
+    }
+}
@@ -0,0 +1,278 @@
+use {
+    crate::{
+        config::ErrorBehaviour,
+        engine::{
+            Reporter,
+            ThreadEvent,
+        },
+        error::Error,
+        failures::Sample,
+        results::WebSocketResults,
+    },
+    anyhow::Result,
+    std::{
+        io::ErrorKind,
+        net::{
+            TcpStream,
+            ToSocketAddrs,
+        },
+        sync::Arc,
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+    tungstenite::{
+        client::IntoClientRequest,
+        http::HeaderMap,
+        stream::MaybeTlsStream,
+        Message,
+        WebSocket,
+    },
+};
+
+/// Connection and message events of a WebSocket worker thread.
+#[derive(Debug)]
+pub(crate) enum Event {
+    Connected(Duration),
+    Sent,
+    Received { round_trip: Option<Duration> },
+    Disconnected(String),
+}
+
+impl Event {
+    pub fn record(self, stats: &mut WebSocketResults) {
+        match self {
+            | Self::Connected(v) => {
+                stats.connects += 1;
+                stats.connect_ms.add(v.as_secs_f64() * 1000.0);
+            },
+            | Self::Sent => stats.sent += 1,
+            | Self::Received { round_trip } => {
+                stats.received += 1;
+                if let Some(v) = round_trip {
+                    stats.round_trip_ms.add(v.as_secs_f64() * 1000.0);
+                }
+            },
+            | Self::Disconnected(reason) => *stats.disconnects.entry(reason).or_default() += 1,
+        }
+    }
+}
+
+/// The WebSocket endpoint of a phase, resolved once per phase.
+pub struct Endpoint {
+    pub url: String,
+    pub header: HeaderMap,
+    /// Timeout for connecting and for waiting on a message.
+    pub timeout: Duration,
+    pub interval: Option<Duration>,
+}
+
+/// A worker thread (virtual user) of a WebSocket phase. It connects lazily,
+/// sends the messages it receives from the producer and waits for a message
+/// from the server after each one. After an error the connection is dropped
+/// and opened again with the next message.
+pub(crate) struct Worker {
+    pub reporter: Reporter,
+    pub endpoint: Arc<Endpoint>,
+    pub on_error: ErrorBehaviour,
+    pub socket: Option<WebSocket<MaybeTlsStream<TcpStream>>>,
+}
+
+impl Worker {
+    pub fn run(mut self, tasks: flume::Receiver<Result<Option<String>>>) {
+        for message in tasks.iter() {
+            self.reporter.dequeued();
+            let message = match message {
+                | Ok(v) => v,
+                | Err(e) => {
+                    self.reporter.unrendered(self.endpoint.url.clone(), &e);
+                    continue;
+                },
+            };
+            let start = Instant::now();
+            match self.exchange(message) {
+                | Ok(v) => {
+                    self.reporter.report(ThreadEvent::Success {
+                        status: v,
+                        response: None,
+                        operation: None,
+                        latency: start.elapsed(),
+                        sample: None,
+                    })
+                },
+                | Err(e) => {
+                    self.reporter.report(ThreadEvent::Error {
+                        operation: None,
+                        latency: start.elapsed(),
+                        sample: Some(Box::new(Sample::error(self.endpoint.url.clone(), &e))),
+                    });
+                    if let Some(v) = &self.on_error.backoff {
+                        std::thread::sleep(Duration::from_millis(v.to_ms()));
+                    }
+                },
+            }
+            if let Some(v) = self.endpoint.interval {
+                std::thread::sleep(v);
+            }
+        }
+        if let Some(mut socket) = self.socket.take() {
+            let _ = socket.close(None);
+            let _ = socket.flush();
+            self.disconnected("finished".to_owned());
+        }
+        self.reporter.report(ThreadEvent::Finished);
+    }
+
+    /// Sends `message` (if any) and returns the next message received.
+    fn exchange(&mut self, message: Option<String>) -> Result<String> {
+        self.connect()?;
+        let socket = self.socket.as_mut().unwrap();
+        let start = Instant::now();
+        let sent = message.is_some();
+        let mut received = None;
+        let mut failure = None;
+        if let Some(v) = message {
+            match socket.send(Message::text(v)) {
+                | Ok(_) => self.reporter.report(ThreadEvent::WebSocket(Event::Sent)),
+                | Err(e) => failure = Some(reason(e)),
+            }
+        }
+        while received.is_none() && failure.is_none() {
+            match socket.read() {
+                | Ok(Message::Text(v)) => received = Some(v),
+                | Ok(Message::Binary(v)) => received = Some(String::from_utf8_lossy(&v).into_owned()),
+                | Ok(Message::Close(Some(v))) => failure = Some(format!("closed by server ({})", u16::from(v.code))),
+                | Ok(Message::Close(None)) => failure = Some("closed by server".to_owned()),
+                | Ok(_) => {},
+                | Err(e) => failure = Some(reason(e)),
+            }
+        }
+
+        match (received, failure) {
+            | (Some(v), None) => {
+                let round_trip = sent.then(|| start.elapsed());
+                self.reporter
+                    .report(ThreadEvent::WebSocket(Event::Received { round_trip }));
+                Ok(v)
+            },
+            | (_, failure) => {
+                let reason = failure.unwrap();
+                self.socket = None;
+                self.disconnected(reason.clone());
+                Err(Error::WebSocket(reason).into())
+            },
+        }
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        if self.socket.is_some() {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let mut request = self.endpoint.url.as_str().into_client_request()?;
+        request.headers_mut().extend(self.endpoint.header.clone());
+
+        let uri = request.uri();
+        let host = uri
+            .host()
+            .ok_or_else(|| Error::WebSocket(format!("{} has no host", self.endpoint.url)))?;
+        let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+            | Some("wss") => 443,
+            | _ => 80,
+        });
+        let addr = (host.trim_start_matches('[').trim_end_matches(']'), port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::WebSocket(format!("can not resolve {}", host)))?;
+        let stream = TcpStream::connect_timeout(&addr, self.endpoint.timeout)?;
+        stream.set_read_timeout(Some(self.endpoint.timeout))?;
+        stream.set_write_timeout(Some(self.endpoint.timeout))?;
+        stream.set_nodelay(true)?;
+
+        let (socket, _) = tungstenite::client_tls(request, stream).map_err(|e| Error::WebSocket(e.to_string()))?;
+        self.socket = Some(socket);
+        self.reporter
+            .report(ThreadEvent::WebSocket(Event::Connected(start.elapsed())));
+        Ok(())
+    }
+
+    fn disconnected(&self, reason: String) {
+        self.reporter
+            .report(ThreadEvent::WebSocket(Event::Disconnected(reason)));
+    }
+}
+
+/// Why a connection was dropped after an error.
+fn reason(error: tungstenite::Error) -> String {
+    match error {
+        | tungstenite::Error::Io(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+            "timeout".to_owned()
+        },
+        | tungstenite::Error::Io(e) => format!("io error ({})", e.kind()),
+        | tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => "closed".to_owned(),
+        | tungstenite::Error::Protocol(_) => "protocol error".to_owned(),
+        | e => format!("error ({})", e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        std::net::TcpListener,
+    };
+
+    /// Accepts a single connection and echoes `n` messages before closing it.
+    fn echo_server(n: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let mut socket = tungstenite::accept(listener.accept().unwrap().0).unwrap();
+            for _ in 0..n {
+                let message = socket.read().unwrap();
+                socket.send(message).unwrap();
+            }
+            socket
+                .close(Some(tungstenite::protocol::CloseFrame {
+                    code: tungstenite::protocol::frame::coding::CloseCode::Normal,
+                    reason: "".into(),
+                }))
+                .unwrap();
+            while socket.read().is_ok() {}
+        });
+        url
+    }
+
+    #[test]
+    fn test_exchange() {
+        let (status_tx, status_rx) = flume::unbounded();
+        let mut worker = Worker {
+            reporter: Reporter::new(0, 0, status_tx, Default::default()),
+            endpoint: Arc::new(Endpoint {
+                url: echo_server(2),
+                header: HeaderMap::new(),
+                timeout: Duration::from_secs(5),
+                interval: None,
+            }),
+            on_error: ErrorBehaviour { backoff: None },
+            socket: None,
+        };
+
+        assert_eq!(worker.exchange(Some("a".into())).unwrap(), "a");
+        assert_eq!(worker.exchange(Some("b".into())).unwrap(), "b");
+        assert!(worker.exchange(Some("c".into())).is_err());
+
+        let mut stats = WebSocketResults::default();
+        for (_, _, event) in status_rx.drain() {
+            if let ThreadEvent::WebSocket(v) = event {
+                v.record(&mut stats);
+            }
+        }
+        assert_eq!(stats.connects, 1);
+        assert_eq!(stats.sent, 3);
+        assert_eq!(stats.received, 2);
+        assert_eq!(stats.round_trip_ms.count, 2);
+        assert_eq!(stats.disconnects.get("closed by server (1000)"), Some(&1));
+    }
+}